@@ -45,7 +45,7 @@ func (q Float16Quantization) Marshal(to []byte, lower float16Vec) error {
 
 func (q Float16Quantization) Unmarshal(data []byte) (float16Vec, error) {
 	out := make(float16Vec, len(data)>>1)
-	for i := 0; i < len(data); i += 4 {
+	for i := 0; i < len(data); i += 2 {
 		bits := binary.LittleEndian.Uint16(data[i:])
 		out[i>>1] = float16.Frombits(bits)
 	}
@@ -1,6 +1,12 @@
 package bbq
 
-import "testing"
+import (
+	"encoding/hex"
+	"encoding/json"
+	"math"
+	"os"
+	"testing"
+)
 
 func TestFloat16Quantization(t *testing.T) {
 	vecs := NewRandVectorSet(1000, *dim, nil)
@@ -49,3 +55,49 @@ func TestFloat16Backend(t *testing.T) {
 	t.Logf("Recall %0.4f\n", recall)
 	t.Logf("\n%s\n%s", rs, qrs)
 }
+
+// The golden vectors are shared with the Rust tests, so both sides agree on
+// the bytes.
+func TestFloat16Golden(t *testing.T) {
+	data, err := os.ReadFile("testdata/float16.json")
+	if err != nil {
+		t.Fatal(err)
+	}
+	var golden struct {
+		Name    string `json:"name"`
+		Vectors []struct {
+			Input   Vector `json:"input"`
+			Bytes   string `json:"bytes"`
+			Decoded Vector `json:"decoded"`
+		} `json:"vectors"`
+	}
+	if err := json.Unmarshal(data, &golden); err != nil {
+		t.Fatal(err)
+	}
+	q := Float16Quantization{}
+	if golden.Name != q.Name() {
+		t.Fatalf("golden name %s, want %s", golden.Name, q.Name())
+	}
+	for _, v := range golden.Vectors {
+		lower, err := q.Lower(v.Input)
+		if err != nil {
+			t.Fatal(err)
+		}
+		buf := make([]byte, q.LowerSize(len(v.Input)))
+		if err := q.Marshal(buf, lower); err != nil {
+			t.Fatal(err)
+		}
+		if got := hex.EncodeToString(buf); got != v.Bytes {
+			t.Fatalf("marshaled %s, want %s", got, v.Bytes)
+		}
+		back, err := q.Unmarshal(buf)
+		if err != nil {
+			t.Fatal(err)
+		}
+		for i, x := range back {
+			if math.Float32bits(x.Float32()) != math.Float32bits(v.Decoded[i]) {
+				t.Fatalf("decoded %v at %d, want %v", x.Float32(), i, v.Decoded[i])
+			}
+		}
+	}
+}
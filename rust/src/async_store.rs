@@ -0,0 +1,168 @@
+use anyhow::{anyhow, Result};
+use std::sync::{Arc, RwLock};
+
+use crate::{
+    Bitmap, CancellationToken, ResultSet, SearchOptions, Snapshot, SnapshotBackend, Vector,
+    VectorBackend, VectorStore, ID,
+};
+
+/// A cloneable async handle over a `VectorStore`.
+///
+/// Every call runs on tokio's blocking pool, so searches and disk I/O never
+/// stall the executor. Reads hold a shared lock and writes an exclusive one.
+/// Dropping a pending read cancels it: before it starts, it never runs, and
+/// a `find_nearest` or `find_nearest_with` already underway stops scoring at
+/// the next candidate. Writes that have been submitted always run to
+/// completion.
+pub struct AsyncVectorStore<E: VectorBackend, B: Bitmap> {
+    inner: Arc<RwLock<VectorStore<E, B>>>,
+}
+
+impl<E: VectorBackend, B: Bitmap> Clone for AsyncVectorStore<E, B> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// Flags the blocking task as abandoned when the awaiting future goes away.
+struct CancelOnDrop(CancellationToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+impl<E, B> AsyncVectorStore<E, B>
+where
+    E: VectorBackend + Send + Sync + 'static,
+    B: Bitmap + Sync + 'static,
+{
+    pub fn new(store: VectorStore<E, B>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(store)),
+        }
+    }
+
+    /// Returns the underlying store if this is the last handle to it.
+    pub fn try_into_inner(self) -> Result<VectorStore<E, B>> {
+        let lock = Arc::try_unwrap(self.inner).map_err(|_| anyhow!("Store is still shared"))?;
+        lock.into_inner()
+            .map_err(|_| anyhow!("Store lock poisoned"))
+    }
+
+    pub async fn add_vector(&self, id: ID, vector: Vector) -> Result<()> {
+        self.write(move |s| s.add_vector(id, &vector)).await
+    }
+
+    pub async fn add_vectors(&self, vectors: Vec<(ID, Vector)>) -> Result<()> {
+        self.write(move |s| s.add_vector_iter(vectors.iter().map(|(id, v)| (*id, v))))
+            .await
+    }
+
+    pub async fn remove_vector(&self, id: ID) -> Result<()> {
+        self.write(move |s| s.remove_vector(id)).await
+    }
+
+    pub async fn find_nearest(
+        &self,
+        target: Vector,
+        k: usize,
+        search_k: usize,
+        spill: usize,
+    ) -> Result<ResultSet> {
+        self.find_nearest_with(target, SearchOptions::new(k, search_k, spill))
+            .await
+    }
+
+    /// Searches with the given options. The search honors any budget in
+    /// `opts`, and is also cancelled if this future is dropped.
+    pub async fn find_nearest_with(
+        &self,
+        target: Vector,
+        opts: SearchOptions,
+    ) -> Result<ResultSet> {
+        let cancel = opts.cancel.as_ref().map(|c| c.child()).unwrap_or_default();
+        let opts = opts.with_cancellation(cancel.clone());
+        self.read_cancellable(cancel, move |s| s.find_nearest_with(&target, &opts))
+            .await
+    }
+
+    pub async fn full_table_scan(&self, target: Vector, k: usize) -> Result<ResultSet> {
+        self.read(move |s| s.full_table_scan(&target, k)).await
+    }
+
+    /// Takes a snapshot under a brief read lock. Searching the snapshot
+    /// holds no lock at all, so it never waits behind a large write.
+    pub async fn snapshot(&self) -> Result<Snapshot<E::Snapshot, B>>
+    where
+        E: SnapshotBackend,
+        E::Snapshot: Send + 'static,
+    {
+        self.read(|s| s.snapshot()).await
+    }
+
+    /// Removes every vector whose TTL has run out, returning how many.
+    pub async fn expire(&self) -> Result<usize> {
+        self.write(|s| s.expire()).await
+    }
+
+    /// Runs `expire` every `period` until the returned task is aborted.
+    pub fn spawn_expiry(&self, period: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        let handle = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                // A failed pass leaves the vectors hidden; the next one retries.
+                let _ = handle.expire().await;
+            }
+        })
+    }
+
+    pub async fn sync(&self) -> Result<()> {
+        self.write(|s| s.sync()).await
+    }
+
+    async fn read<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&VectorStore<E, B>) -> Result<T> + Send + 'static,
+    {
+        self.read_cancellable(CancellationToken::new(), f).await
+    }
+
+    async fn read_cancellable<T, F>(&self, cancel: CancellationToken, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&VectorStore<E, B>) -> Result<T> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        let guard = CancelOnDrop(cancel.clone());
+        let out = tokio::task::spawn_blocking(move || {
+            if cancel.is_cancelled() {
+                return Err(anyhow!("Cancelled"));
+            }
+            let store = inner.read().map_err(|_| anyhow!("Store lock poisoned"))?;
+            f(&store)
+        })
+        .await?;
+        drop(guard);
+        out
+    }
+
+    async fn write<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut VectorStore<E, B>) -> Result<T> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut store = inner.write().map_err(|_| anyhow!("Store lock poisoned"))?;
+            f(&mut store)
+        })
+        .await?
+    }
+}
@@ -12,17 +12,38 @@ pub struct BackendInfo {
 
 pub trait VectorBackend {
     fn put_vector(&mut self, id: ID, v: &Vector) -> Result<()>;
+    fn remove_vector(&mut self, id: ID) -> Result<()>;
     fn compute_similarity(&self, target: &Vector, target_id: ID) -> Result<f32>;
+    /// Scores at the best precision the backend holds, for re-ranking.
+    /// Backends that keep a single copy score it as usual.
+    fn compute_exact_similarity(&self, target: &Vector, target_id: ID) -> Result<f32> {
+        self.compute_similarity(target, target_id)
+    }
+    /// Prepares to score `target` against many stored vectors, as
+    /// `compute_similarity` would. Searches build one and call it for each
+    /// candidate, so per-query work is done once.
+    fn scorer<'a>(&'a self, target: &'a Vector) -> Result<impl Fn(ID) -> Result<f32> + 'a> {
+        Ok(move |id| self.compute_similarity(target, id))
+    }
+    /// Like `scorer`, as `compute_exact_similarity` would score.
+    fn exact_scorer<'a>(&'a self, target: &'a Vector) -> Result<impl Fn(ID) -> Result<f32> + 'a> {
+        Ok(move |id| self.compute_exact_similarity(target, id))
+    }
+    /// Returns the stored (normalized, and possibly quantized) vector.
+    fn get_vector(&self, id: ID) -> Result<Vector>;
     fn info(&self) -> BackendInfo;
+    /// The quantizer, with whatever it was trained on, as JSON, for checking
+    /// that two backends quantize alike.
+    fn quantizer_state(&self) -> Result<serde_json::Value>;
     fn iter_vector_ids(&self) -> impl Iterator<Item = ID>;
     fn vector_exists(&self, id: ID) -> bool;
     fn close(self) -> Result<()>;
 
     fn find_nearest(&self, target: &Vector, k: usize) -> Result<ResultSet> {
         let mut set = ResultSet::new(k);
+        let score = self.scorer(target)?;
         for id in self.iter_vector_ids() {
-            let sim = self.compute_similarity(target, id)?;
-            set.add_result(id, sim);
+            set.add_result(id, score(id)?);
         }
         Ok(set)
     }
@@ -34,7 +55,17 @@ pub trait VectorBackend {
         Ok(())
     }
 
-    fn save_bitmap(&mut self, _basis: usize, _index: usize, _bitmap: &impl Bitmap) -> Result<()> {
+    fn save_bitmap(&mut self, _basis: usize, _index: i32, _bitmap: &impl Bitmap) -> Result<()> {
+        Ok(())
+    }
+
+    /// Loads an auxiliary blob previously stored under `name`, for layers
+    /// that keep their own state alongside the index.
+    fn load_blob(&self, _name: &str) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    fn save_blob(&mut self, _name: &str, _data: &[u8]) -> Result<()> {
         Ok(())
     }
 
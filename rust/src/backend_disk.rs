@@ -1,71 +1,232 @@
-use anyhow::Result;
-use std::{collections::HashMap, path::PathBuf};
+use anyhow::{anyhow, Result};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    quantization::Quantization, vector_file::VectorFile, Basis, Bitmap, Vector, VectorBackend, ID,
+    backend::BackendInfo, quantization::Quantization, snapshot::SnapshotBackend,
+    vector_file::VectorFile, Basis, Bitmap, Vector, VectorBackend, ID,
 };
 
 #[derive(Default)]
 pub struct DiskBackend<Q: Quantization> {
     dir: PathBuf,
     metadata: DiskMetadata,
-    vector_files: HashMap<usize, VectorFile<Q>>,
-    token: u64,
+    quantizer: Q,
+    pages: Pages<Q>,
+    vector_count: usize,
 }
 
 #[derive(Serialize, Deserialize, Default)]
 pub(crate) struct DiskMetadata {
     pub dimensions: usize,
+    #[serde(default)]
+    pub n_basis: usize,
     pub quantization: String,
+    /// The quantizer, with whatever it was trained on, as JSON.
+    #[serde(default)]
+    pub quantizer: serde_json::Value,
     pub vecs_per_file: usize,
     pub vec_files: Vec<usize>,
+    /// How many times each file has been copied for a snapshot; the file
+    /// name carries it.
+    #[serde(default)]
+    pub generations: HashMap<usize, u64>,
 }
 
 const DEFAULT_VECS_PER_FILE: usize = 200_000;
 
-impl<Q: Quantization> DiskBackend<Q> {
-    pub fn open(path: PathBuf, dimensions: usize) -> Result<Self> {
-        let mut token: u64 = rand::random();
-        if token == 0 {
-            token = 1;
+// The mapped vector files by key, each shared with any snapshots that still
+// read it.
+#[derive(Default, Clone)]
+struct Pages<Q: Quantization> {
+    files: HashMap<usize, Arc<VectorFile<Q>>>,
+    per_file: usize,
+}
+
+impl<Q: Quantization> Pages<Q> {
+    fn locate(&self, id: ID) -> (usize, usize) {
+        let uid = id as usize;
+        (uid / self.per_file, uid % self.per_file)
+    }
+
+    fn find(&self, id: ID) -> Result<(&VectorFile<Q>, usize)> {
+        let (key, offset) = self.locate(id);
+        match self.files.get(&key) {
+            Some(vf) if vf.exists_at(offset) => Ok((vf, offset)),
+            _ => Err(anyhow!("No vector present")),
         }
+    }
+
+    fn exists(&self, id: ID) -> bool {
+        let (key, offset) = self.locate(id);
+        match self.files.get(&key) {
+            Some(vf) => vf.exists_at(offset),
+            None => false,
+        }
+    }
+
+    fn read(&self, q: &Q, id: ID) -> Result<Q::Lower> {
+        let (vf, offset) = self.find(id)?;
+        vf.read_at(q, offset)
+    }
+
+    fn compare(&self, q: &Q, target: &Vector, id: ID) -> Result<f32> {
+        let (vf, offset) = self.find(id)?;
+        vf.compare_at(q, offset, target)
+    }
+
+    fn scorer<'a>(
+        &'a self,
+        q: &'a Q,
+        target: &'a Vector,
+    ) -> Result<impl Fn(ID) -> Result<f32> + 'a> {
+        let score = q.bytes_scorer(target)?;
+        Ok(move |id| {
+            let (vf, offset) = self.find(id)?;
+            score(vf.bytes_at(offset)?)
+        })
+    }
+
+    fn iter_ids(&self) -> impl Iterator<Item = ID> + '_ {
+        let mut keys: Vec<usize> = self.files.keys().copied().collect();
+        keys.sort();
+        keys.into_iter().flat_map(move |key| {
+            let vf = &self.files[&key];
+            (0..self.per_file)
+                .filter(|offset| vf.exists_at(*offset))
+                .map(move |offset| (key * self.per_file + offset) as ID)
+        })
+    }
+}
+
+fn page_file_name(key: usize, generation: u64) -> String {
+    match generation {
+        0 => format!("{:x}.vec", key),
+        g => format!("{:x}-{:x}.vec", key, g),
+    }
+}
+
+impl<Q: Quantization> DiskBackend<Q> {
+    pub fn open(path: PathBuf, dimensions: usize, n_basis: usize) -> Result<Self> {
+        Self::open_inner(path, dimensions, n_basis, None)
+    }
+
+    /// Opens a store that quantizes with a given, perhaps trained,
+    /// quantizer. A new store keeps it in its metadata; an existing one
+    /// must have been created with the same quantizer.
+    pub fn open_with_quantizer(
+        path: PathBuf,
+        dimensions: usize,
+        n_basis: usize,
+        quantizer: Q,
+    ) -> Result<Self> {
+        Self::open_inner(path, dimensions, n_basis, Some(quantizer))
+    }
+
+    fn open_inner(
+        path: PathBuf,
+        dimensions: usize,
+        n_basis: usize,
+        quantizer: Option<Q>,
+    ) -> Result<Self> {
+        let given = quantizer.is_some();
+        let quantizer = quantizer.unwrap_or_default();
         let mut s = Self {
             dir: path,
             metadata: DiskMetadata {
                 dimensions,
-                quantization: Q::name().into(),
+                n_basis,
+                quantization: quantizer.name().into(),
+                quantizer: serde_json::to_value(&quantizer)?,
                 vecs_per_file: DEFAULT_VECS_PER_FILE,
                 vec_files: Vec::new(),
+                generations: HashMap::new(),
             },
-            token,
+            quantizer,
             ..Default::default()
         };
-        s.open_files()?;
+        s.open_files(given)?;
+        s.quantizer.set_dimensions(s.metadata.dimensions);
         Ok(s)
     }
 
-    fn open_files(&mut self) -> Result<()> {
+    pub fn quantizer(&self) -> &Q {
+        &self.quantizer
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    fn open_files(&mut self, quantizer_given: bool) -> Result<()> {
         let metadata_path = self.dir.join("metadata.json");
         if !metadata_path.exists() {
             return self.create_new();
         }
         let metadata_contents = std::fs::read_to_string(&metadata_path)?;
         let metadata: DiskMetadata = serde_json::from_str(&metadata_contents)?;
+        if metadata.quantization != self.quantizer.name() {
+            return Err(anyhow!(
+                "Store was written with quantization {}, not {}",
+                metadata.quantization,
+                self.quantizer.name()
+            ));
+        }
+        if metadata.dimensions != self.metadata.dimensions {
+            return Err(anyhow!(
+                "Store has {} dimensions, not {}",
+                metadata.dimensions,
+                self.metadata.dimensions
+            ));
+        }
+        // Stores from before quantizers were kept use the default.
+        if !metadata.quantizer.is_null() {
+            if quantizer_given && metadata.quantizer != self.metadata.quantizer {
+                return Err(anyhow!(
+                    "Store was written with a different {} quantizer",
+                    metadata.quantization
+                ));
+            }
+            self.quantizer = serde_json::from_value(metadata.quantizer.clone())?;
+        }
+        let quantizer = std::mem::take(&mut self.metadata.quantizer);
         self.metadata = metadata;
+        if self.metadata.quantizer.is_null() {
+            self.metadata.quantizer = quantizer;
+        }
+        self.pages.per_file = self.metadata.vecs_per_file;
         for vf in self.metadata.vec_files.iter() {
-            let vector_file = VectorFile::<Q>::create_or_open(
+            let vector_file = VectorFile::create_or_open(
                 self.make_pagefile_path(vf),
-                self.metadata.dimensions,
+                self.quantizer.vector_size(self.metadata.dimensions),
                 self.metadata.vecs_per_file,
             )?;
-            self.vector_files.insert(*vf, vector_file);
+            self.vector_count += vector_file.count();
+            self.pages.files.insert(*vf, Arc::new(vector_file));
+        }
+        // Old generations a snapshot held when the process stopped.
+        let live: HashSet<PathBuf> = self
+            .metadata
+            .vec_files
+            .iter()
+            .map(|vf| self.make_pagefile_path(vf))
+            .collect();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "vec") && !live.contains(&path) {
+                std::fs::remove_file(path)?;
+            }
         }
         Ok(())
     }
 
     fn create_new(&mut self) -> Result<()> {
+        self.pages.per_file = self.metadata.vecs_per_file;
         std::fs::create_dir_all(self.dir.clone())?;
         self.save_metadata()
     }
@@ -79,55 +240,272 @@ impl<Q: Quantization> DiskBackend<Q> {
     }
 
     fn make_pagefile_path(&self, key: &usize) -> PathBuf {
-        self.dir.join(format!("{:x}.vec", key))
+        let generation = self.metadata.generations.get(key).copied().unwrap_or(0);
+        self.dir.join(page_file_name(*key, generation))
+    }
+
+    fn make_bitmap_path(&self, basis: usize, index: i32) -> PathBuf {
+        self.dir
+            .join(format!("{:04x}-{:08x}.bmap", basis as u16, index as u32))
+    }
+
+    fn make_blob_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.blob", name))
+    }
+
+    // A file a snapshot still reads is copied, under the next generation,
+    // before it's written. The snapshot keeps the old file, which is
+    // deleted once the last snapshot holding it is dropped.
+    fn page_mut(&mut self, key: usize) -> Result<&mut VectorFile<Q>> {
+        let page = self.pages.files[&key].clone();
+        if Arc::strong_count(&page) > 2 {
+            let generation = self.metadata.generations.get(&key).copied().unwrap_or(0) + 1;
+            let path = self.dir.join(page_file_name(key, generation));
+            std::fs::copy(page.path(), &path)?;
+            let copy = VectorFile::create_or_open(
+                path,
+                self.quantizer.vector_size(self.metadata.dimensions),
+                self.metadata.vecs_per_file,
+            )?;
+            self.pages.files.insert(key, Arc::new(copy));
+            self.metadata.generations.insert(key, generation);
+            self.save_metadata()?;
+            page.retire();
+        }
+        drop(page);
+        let page = self.pages.files.get_mut(&key).unwrap();
+        Arc::get_mut(page).ok_or(anyhow!("Vector file {} is still shared", key))
+    }
+
+    fn create_page(&mut self, key: usize) -> Result<()> {
+        let vector_file = VectorFile::create_or_open(
+            self.make_pagefile_path(&key),
+            self.quantizer.vector_size(self.metadata.dimensions),
+            self.metadata.vecs_per_file,
+        )?;
+        self.pages.files.insert(key, Arc::new(vector_file));
+        self.metadata.vec_files.push(key);
+        self.save_metadata()
     }
 }
 
 impl<Q: Quantization> VectorBackend for DiskBackend<Q> {
     fn put_vector(&mut self, id: ID, v: &Vector) -> Result<()> {
-        todo!()
+        if v.len() != self.metadata.dimensions {
+            return Err(anyhow!("dimensions don't match"));
+        }
+        let (key, offset) = self.pages.locate(id);
+        if !self.pages.files.contains_key(&key) {
+            self.create_page(key)?;
+        }
+        let mut insert = v.clone();
+        crate::vector::normalize(&mut insert);
+        let l = self.quantizer.lower(insert)?;
+        let existed = self.pages.exists(id);
+        let quantizer = self.quantizer.clone();
+        self.page_mut(key)?.write_at(&quantizer, offset, &l)?;
+        if !existed {
+            self.vector_count += 1;
+        }
+        Ok(())
+    }
+
+    fn remove_vector(&mut self, id: ID) -> Result<()> {
+        if self.pages.exists(id) {
+            let (key, offset) = self.pages.locate(id);
+            self.page_mut(key)?.clear_at(offset)?;
+            self.vector_count -= 1;
+        }
+        Ok(())
     }
 
     fn compute_similarity(&self, target: &Vector, target_id: ID) -> Result<f32> {
-        todo!()
+        self.pages.compare(&self.quantizer, target, target_id)
     }
 
-    fn info(&self) -> crate::backend::BackendInfo {
-        todo!()
+    fn scorer<'a>(&'a self, target: &'a Vector) -> Result<impl Fn(ID) -> Result<f32> + 'a> {
+        self.pages.scorer(&self.quantizer, target)
+    }
+
+    fn get_vector(&self, id: ID) -> Result<Vector> {
+        self.quantizer.raise(&self.pages.read(&self.quantizer, id)?)
+    }
+
+    fn info(&self) -> BackendInfo {
+        BackendInfo {
+            quantization: self.metadata.quantization.clone(),
+            has_index_data: self.dir.join("bases").exists(),
+            dimensions: self.metadata.dimensions,
+            n_basis: self.metadata.n_basis,
+            vector_count: self.vector_count,
+        }
+    }
+
+    fn quantizer_state(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(&self.quantizer)?)
     }
 
     fn iter_vector_ids(&self) -> impl Iterator<Item = ID> {
-        0..(self.metadata.vecs_per_file * self.metadata.vec_files.len()) as ID
+        self.pages.iter_ids()
     }
 
     fn vector_exists(&self, id: ID) -> bool {
-        todo!()
+        self.pages.exists(id)
     }
 
     fn close(self) -> Result<()> {
-        todo!()
+        self.sync()
     }
 
     fn load_bases(&self) -> Result<Option<Vec<Basis>>> {
-        todo!()
+        let path = self.dir.join("bases");
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = std::fs::read(path)?;
+        let dim = self.metadata.dimensions;
+        if data.len() % (4 * dim * dim) != 0 {
+            return Err(anyhow!("Bases file has the wrong size"));
+        }
+        let floats: Vec<f32> = data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        let bases = floats
+            .chunks_exact(dim * dim)
+            .map(|basis| basis.chunks_exact(dim).map(|v| v.to_vec()).collect())
+            .collect();
+        Ok(Some(bases))
     }
 
     fn load_bitmap<B: Bitmap>(&mut self, basis: usize, index: i32) -> Result<Option<B>> {
-        todo!()
+        let path = self.make_bitmap_path(basis, index);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(B::from_bytes(&std::fs::read(path)?)?))
     }
 
-    fn save_bases(&mut self, _bases: &[Basis]) -> Result<()> {
-        Ok(())
+    fn save_bases(&mut self, bases: &[Basis]) -> Result<()> {
+        let mut buf = Vec::new();
+        for basis in bases {
+            for v in basis {
+                for f in v {
+                    buf.extend_from_slice(&f.to_le_bytes());
+                }
+            }
+        }
+        Ok(std::fs::write(self.dir.join("bases"), buf)?)
     }
 
-    fn save_bitmap(&mut self, _basis: usize, _index: usize, _bitmap: &impl Bitmap) -> Result<()> {
-        Ok(())
+    fn save_bitmap(&mut self, basis: usize, index: i32, bitmap: &impl Bitmap) -> Result<()> {
+        let path = self.make_bitmap_path(basis, index);
+        if bitmap.is_empty() && !path.exists() {
+            return Ok(());
+        }
+        Ok(std::fs::write(path, bitmap.to_bytes()?)?)
+    }
+
+    fn load_blob(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.make_blob_path(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(std::fs::read(path)?))
+    }
+
+    fn save_blob(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        Ok(std::fs::write(self.make_blob_path(name), data)?)
     }
 
     fn sync(&self) -> Result<()> {
-        for v in self.vector_files.values() {
+        for v in self.pages.files.values() {
             v.flush()?
         }
         self.save_metadata()
     }
 }
+
+impl<Q: Quantization> SnapshotBackend for DiskBackend<Q> {
+    type Snapshot = DiskSnapshot<Q>;
+
+    fn snapshot(&self) -> Result<Self::Snapshot> {
+        Ok(DiskSnapshot {
+            pages: self.pages.clone(),
+            quantizer: self.quantizer.clone(),
+            dimensions: self.metadata.dimensions,
+            n_basis: self.metadata.n_basis,
+            vector_count: self.vector_count,
+        })
+    }
+}
+
+/// A read-only, point-in-time view of a `DiskBackend`, from `snapshot`.
+///
+/// It shares the backend's mapped files rather than copying them. The
+/// backend copies a file the first time it writes to it while a snapshot
+/// still holds it, so a snapshot costs nothing to take, and each file
+/// written afterwards costs one copy on disk until the snapshot is dropped.
+pub struct DiskSnapshot<Q: Quantization> {
+    pages: Pages<Q>,
+    quantizer: Q,
+    dimensions: usize,
+    n_basis: usize,
+    vector_count: usize,
+}
+
+impl<Q: Quantization> VectorBackend for DiskSnapshot<Q> {
+    fn put_vector(&mut self, _id: ID, _v: &Vector) -> Result<()> {
+        Err(anyhow!("Disk snapshots are read-only"))
+    }
+
+    fn remove_vector(&mut self, _id: ID) -> Result<()> {
+        Err(anyhow!("Disk snapshots are read-only"))
+    }
+
+    fn compute_similarity(&self, target: &Vector, target_id: ID) -> Result<f32> {
+        self.pages.compare(&self.quantizer, target, target_id)
+    }
+
+    fn scorer<'a>(&'a self, target: &'a Vector) -> Result<impl Fn(ID) -> Result<f32> + 'a> {
+        self.pages.scorer(&self.quantizer, target)
+    }
+
+    fn get_vector(&self, id: ID) -> Result<Vector> {
+        self.quantizer.raise(&self.pages.read(&self.quantizer, id)?)
+    }
+
+    fn info(&self) -> BackendInfo {
+        BackendInfo {
+            quantization: self.quantizer.name().into(),
+            has_index_data: false,
+            dimensions: self.dimensions,
+            n_basis: self.n_basis,
+            vector_count: self.vector_count,
+        }
+    }
+
+    fn quantizer_state(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(&self.quantizer)?)
+    }
+
+    fn iter_vector_ids(&self) -> impl Iterator<Item = ID> {
+        self.pages.iter_ids()
+    }
+
+    fn vector_exists(&self, id: ID) -> bool {
+        self.pages.exists(id)
+    }
+
+    fn close(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn load_bases(&self) -> Result<Option<Vec<Basis>>> {
+        Ok(None)
+    }
+
+    fn load_bitmap<B: Bitmap>(&mut self, _basis: usize, _index: i32) -> Result<Option<B>> {
+        Ok(None)
+    }
+}
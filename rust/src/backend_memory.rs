@@ -1,7 +1,4 @@
-use std::{
-    cmp::min,
-    sync::{Arc, Mutex},
-};
+use std::sync::{Arc, Mutex};
 
 use anyhow::{anyhow, Result};
 use rand::RngCore;
@@ -9,11 +6,14 @@ use rand::RngCore;
 use crate::{
     backend::{BackendInfo, VectorBackend},
     quantization::Quantization,
+    slot_map::SlotMap,
+    snapshot::SnapshotBackend,
     Vector, ID,
 };
 
 pub struct QuantizedMemoryBackend<Q: Quantization> {
-    vecs: Vec<Option<Q::Lower>>,
+    vecs: SlotMap<Q::Lower>,
+    quantizer: Q,
     dimensions: usize,
     n_basis: usize,
     rng: Option<Arc<Mutex<Box<dyn RngCore + Send>>>>,
@@ -23,14 +23,25 @@ pub type MemoryBackend = QuantizedMemoryBackend<crate::quantization::NoQuantizat
 
 impl<Q: Quantization> QuantizedMemoryBackend<Q> {
     pub fn new(dimensions: usize, n_basis: usize) -> Result<Self> {
+        Self::with_quantizer(dimensions, n_basis, Q::default())
+    }
+
+    /// Creates a backend that quantizes with a given, perhaps trained,
+    /// quantizer.
+    pub fn with_quantizer(dimensions: usize, n_basis: usize, quantizer: Q) -> Result<Self> {
         Ok(Self {
-            vecs: Vec::new(),
+            vecs: SlotMap::default(),
+            quantizer,
             dimensions,
             n_basis,
             rng: None,
         })
     }
 
+    pub fn quantizer(&self) -> &Q {
+        &self.quantizer
+    }
+
     pub fn set_rng(&mut self, rng: Box<dyn RngCore + Send>) {
         self.rng = Some(Arc::new(Mutex::new(rng)));
     }
@@ -41,26 +52,35 @@ impl<Q: Quantization> VectorBackend for QuantizedMemoryBackend<Q> {
         if v.len() != self.dimensions {
             return Err(anyhow!("dimensions don't match"));
         }
-        let uid = id as usize;
-        if self.vecs.len() <= uid {
-            if self.vecs.capacity() == uid {
-                self.vecs.reserve(min(self.vecs.capacity(), 1024 * 1024))
-            }
-            self.vecs.resize(uid + 1, None);
-        }
         let mut insert = v.clone();
         crate::vector::normalize(&mut insert);
-        let l = Q::lower(insert)?;
-        self.vecs[uid] = Some(l);
+        let l = self.quantizer.lower(insert)?;
+        self.vecs.insert(id, l);
+        Ok(())
+    }
+
+    fn remove_vector(&mut self, id: ID) -> Result<()> {
+        self.vecs.remove(id);
         Ok(())
     }
 
     fn compute_similarity(&self, target: &Vector, target_id: crate::ID) -> Result<f32> {
         // Make sure it's normalized!
-        let v = self.vecs[target_id as usize]
-            .as_ref()
+        let v = self
+            .vecs
+            .get(target_id)
             .ok_or(anyhow!("No vector present"))?;
-        Q::compare(target, v)
+        self.quantizer.compare(target, v)
+    }
+
+    fn scorer<'a>(&'a self, target: &'a Vector) -> Result<impl Fn(ID) -> Result<f32> + 'a> {
+        let score = self.quantizer.scorer(target)?;
+        Ok(move |id| score(self.vecs.get(id).ok_or(anyhow!("No vector present"))?))
+    }
+
+    fn get_vector(&self, id: ID) -> Result<Vector> {
+        let v = self.vecs.get(id).ok_or(anyhow!("No vector present"))?;
+        self.quantizer.raise(v)
     }
 
     fn info(&self) -> crate::backend::BackendInfo {
@@ -69,28 +89,24 @@ impl<Q: Quantization> VectorBackend for QuantizedMemoryBackend<Q> {
             dimensions: self.dimensions,
             n_basis: self.n_basis,
             vector_count: self.vecs.len(),
-            quantization: Q::name().into(),
+            quantization: self.quantizer.name().into(),
         }
     }
 
+    fn quantizer_state(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::to_value(&self.quantizer)?)
+    }
+
     fn iter_vector_ids(&self) -> impl Iterator<Item = ID> {
-        self.vecs
-            .iter()
-            .enumerate()
-            .filter(|(_, v)| v.is_some())
-            .map(|(k, _)| k as ID)
+        self.vecs.iter_ids()
     }
 
     fn vector_exists(&self, id: ID) -> bool {
-        let v = self.vecs.get(id as usize);
-        match v {
-            Some(x) => x.is_some(),
-            None => false,
-        }
+        self.vecs.contains(id)
     }
 
     fn close(self) -> Result<()> {
-        todo!()
+        Ok(())
     }
 
     fn load_bases(&self) -> Result<Option<Vec<crate::Basis>>> {
@@ -101,3 +117,18 @@ impl<Q: Quantization> VectorBackend for QuantizedMemoryBackend<Q> {
         Ok(None)
     }
 }
+
+impl<Q: Quantization> SnapshotBackend for QuantizedMemoryBackend<Q> {
+    type Snapshot = Self;
+
+    // Pages are shared until either side writes to them.
+    fn snapshot(&self) -> Result<Self> {
+        Ok(Self {
+            vecs: self.vecs.clone(),
+            quantizer: self.quantizer.clone(),
+            dimensions: self.dimensions,
+            n_basis: self.n_basis,
+            rng: self.rng.clone(),
+        })
+    }
+}
@@ -0,0 +1,235 @@
+use anyhow::{anyhow, Result};
+
+use crate::{
+    backend::{BackendInfo, VectorBackend},
+    snapshot::SnapshotBackend,
+    Basis, Bitmap, ResultSet, Vector, ID,
+};
+
+/// Keeps two copies of every vector: a compact `coarse` one that scores
+/// candidates, and a `fine` one, usually full precision, that re-scores the
+/// best of them when the search asks for a `rerank_k`.
+///
+/// The index (bases, bitmaps and blobs) lives with the fine backend, so a
+/// disk-backed fine copy persists it while the coarse copy can be memory.
+pub struct TwoStageBackend<C: VectorBackend, F: VectorBackend> {
+    coarse: C,
+    fine: F,
+}
+
+impl<C: VectorBackend, F: VectorBackend> TwoStageBackend<C, F> {
+    /// Pairs the two backends. The coarse copy is brought in line with the
+    /// fine one, which holds the truth: vectors it's missing are added and
+    /// ones the fine copy doesn't have are dropped. So an in-memory coarse
+    /// copy can sit in front of a reopened disk store.
+    pub fn new(mut coarse: C, fine: F) -> Result<Self> {
+        let (ci, fi) = (coarse.info(), fine.info());
+        if ci.dimensions != fi.dimensions {
+            return Err(anyhow!(
+                "Coarse backend has {} dimensions, fine backend has {}",
+                ci.dimensions,
+                fi.dimensions
+            ));
+        }
+        // Equal counts don't mean equal IDs, so always compare the sets.
+        let stale: Vec<ID> = coarse
+            .iter_vector_ids()
+            .filter(|id| !fine.vector_exists(*id))
+            .collect();
+        for id in stale {
+            coarse.remove_vector(id)?;
+        }
+        for id in fine.iter_vector_ids() {
+            if !coarse.vector_exists(id) {
+                coarse.put_vector(id, &fine.get_vector(id)?)?;
+            }
+        }
+        Ok(Self { coarse, fine })
+    }
+
+    pub fn coarse(&self) -> &C {
+        &self.coarse
+    }
+
+    pub fn fine(&self) -> &F {
+        &self.fine
+    }
+}
+
+impl<C: VectorBackend, F: VectorBackend> VectorBackend for TwoStageBackend<C, F> {
+    fn put_vector(&mut self, id: ID, v: &Vector) -> Result<()> {
+        let old = match self.fine.vector_exists(id) {
+            true => Some(self.fine.get_vector(id)?),
+            false => None,
+        };
+        self.fine.put_vector(id, v)?;
+        if let Err(e) = self.coarse.put_vector(id, v) {
+            // Put back the vector we replaced, or leave neither copy rather
+            // than a fine one the coarse pass can never find.
+            match old {
+                Some(old) => self.fine.put_vector(id, &old)?,
+                None => self.fine.remove_vector(id)?,
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn remove_vector(&mut self, id: ID) -> Result<()> {
+        self.coarse.remove_vector(id)?;
+        self.fine.remove_vector(id)
+    }
+
+    fn compute_similarity(&self, target: &Vector, target_id: ID) -> Result<f32> {
+        self.coarse.compute_similarity(target, target_id)
+    }
+
+    fn compute_exact_similarity(&self, target: &Vector, target_id: ID) -> Result<f32> {
+        self.fine.compute_exact_similarity(target, target_id)
+    }
+
+    fn scorer<'a>(&'a self, target: &'a Vector) -> Result<impl Fn(ID) -> Result<f32> + 'a> {
+        self.coarse.scorer(target)
+    }
+
+    fn exact_scorer<'a>(&'a self, target: &'a Vector) -> Result<impl Fn(ID) -> Result<f32> + 'a> {
+        self.fine.exact_scorer(target)
+    }
+
+    fn get_vector(&self, id: ID) -> Result<Vector> {
+        self.fine.get_vector(id)
+    }
+
+    fn info(&self) -> BackendInfo {
+        let coarse = self.coarse.info();
+        let fine = self.fine.info();
+        BackendInfo {
+            quantization: format!("{}+{}", coarse.quantization, fine.quantization),
+            ..fine
+        }
+    }
+
+    fn quantizer_state(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::json!([
+            self.coarse.quantizer_state()?,
+            self.fine.quantizer_state()?
+        ]))
+    }
+
+    fn iter_vector_ids(&self) -> impl Iterator<Item = ID> {
+        self.fine.iter_vector_ids()
+    }
+
+    fn vector_exists(&self, id: ID) -> bool {
+        self.fine.vector_exists(id)
+    }
+
+    fn close(self) -> Result<()> {
+        self.coarse.close()?;
+        self.fine.close()
+    }
+
+    // A full scan is the exact answer, so it uses the fine copy.
+    fn find_nearest(&self, target: &Vector, k: usize) -> Result<ResultSet> {
+        self.fine.find_nearest(target, k)
+    }
+
+    fn load_bases(&self) -> Result<Option<Vec<Basis>>> {
+        self.fine.load_bases()
+    }
+
+    fn load_bitmap<B: Bitmap>(&mut self, basis: usize, index: i32) -> Result<Option<B>> {
+        self.fine.load_bitmap(basis, index)
+    }
+
+    fn save_bases(&mut self, bases: &[Basis]) -> Result<()> {
+        self.fine.save_bases(bases)
+    }
+
+    fn save_bitmap(&mut self, basis: usize, index: i32, bitmap: &impl Bitmap) -> Result<()> {
+        self.fine.save_bitmap(basis, index, bitmap)
+    }
+
+    fn load_blob(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        self.fine.load_blob(name)
+    }
+
+    fn save_blob(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        self.fine.save_blob(name, data)
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.coarse.sync()?;
+        self.fine.sync()
+    }
+}
+
+impl<C: SnapshotBackend, F: SnapshotBackend> SnapshotBackend for TwoStageBackend<C, F> {
+    type Snapshot = TwoStageBackend<C::Snapshot, F::Snapshot>;
+
+    fn snapshot(&self) -> Result<Self::Snapshot> {
+        Ok(TwoStageBackend {
+            coarse: self.coarse.snapshot()?,
+            fine: self.fine.snapshot()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BF16Quantization, MemoryBackend, QuantizedMemoryBackend};
+
+    #[test]
+    fn scores_coarse_and_fine() -> Result<()> {
+        let coarse = QuantizedMemoryBackend::<BF16Quantization>::new(4, 2)?;
+        let mut fine = MemoryBackend::new(4, 2)?;
+        fine.put_vector(7, &vec![0.3, 0.1, 0.7, 0.2])?;
+        let mut be = TwoStageBackend::new(coarse, fine)?;
+        assert!(be.coarse().vector_exists(7));
+        be.put_vector(8, &vec![0.1, 0.9, 0.1, 0.4])?;
+        assert_eq!(be.info().quantization, "bf16+none");
+        assert_eq!(be.info().vector_count, 2);
+
+        let target = vec![0.3, 0.1, 0.7, 0.2];
+        let exact = be.compute_exact_similarity(&target, 7)?;
+        assert_eq!(exact, be.fine().compute_similarity(&target, 7)?);
+        assert_eq!(
+            be.compute_similarity(&target, 7)?,
+            be.coarse().compute_similarity(&target, 7)?
+        );
+        assert_eq!(be.get_vector(8)?, be.fine().get_vector(8)?);
+
+        be.remove_vector(7)?;
+        assert!(!be.coarse().vector_exists(7));
+        assert!(!be.fine().vector_exists(7));
+        Ok(())
+    }
+
+    #[test]
+    fn syncs_coarse_ids_with_fine() -> Result<()> {
+        let mut coarse = QuantizedMemoryBackend::<BF16Quantization>::new(4, 2)?;
+        let mut fine = MemoryBackend::new(4, 2)?;
+        // Same count, different IDs.
+        coarse.put_vector(1, &vec![0.3, 0.1, 0.7, 0.2])?;
+        fine.put_vector(2, &vec![0.1, 0.9, 0.1, 0.4])?;
+        let be = TwoStageBackend::new(coarse, fine)?;
+        assert!(!be.coarse().vector_exists(1));
+        assert!(be.coarse().vector_exists(2));
+        assert_eq!(be.coarse().info().vector_count, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn failed_overwrite_keeps_old_vector() -> Result<()> {
+        // An untrained product quantizer can't store anything, so every
+        // coarse put fails.
+        let coarse = QuantizedMemoryBackend::<crate::ProductQuantization<2>>::new(4, 2)?;
+        let mut be = TwoStageBackend::new(coarse, MemoryBackend::new(4, 2)?)?;
+        be.fine.put_vector(7, &vec![0.3, 0.1, 0.7, 0.2])?;
+        let before = be.get_vector(7)?;
+        assert!(be.put_vector(7, &vec![0.1, 0.9, 0.1, 0.4]).is_err());
+        assert_eq!(be.get_vector(7)?, before);
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::Result;
+
+use crate::{
+    counting_bitmap::CountingBitmap, result::SearchResult, Bitmap, Vector, VectorBackend,
+    VectorStore,
+};
+
+/// Pages through search results without re-running the query, best
+/// effort.
+///
+/// The cursor keeps the counting bitmap for the query and scores one
+/// counting layer at a time, best layer first. When the scored candidates
+/// run out it widens to the next layer down, and after the last layer it
+/// spills into more faces.
+///
+/// Each page is ranked only among the candidates scored so far, so pages
+/// are not in global order: a later, wider layer can turn up a better match
+/// than one already returned. Only a full table scan can promise that, so
+/// sort the pages you have collected if their order matters.
+pub struct BestEffortCursor<'a, E: VectorBackend, B: Bitmap> {
+    store: &'a VectorStore<E, B>,
+    target: Vector,
+    spill: usize,
+    counts: CountingBitmap<B>,
+    // The next counting layer to widen into, if any remain at this spill.
+    next_level: Option<usize>,
+    seen: B,
+    // Scored but not yet returned, sorted worst-first so the best pops off the end.
+    pending: Vec<SearchResult>,
+    returned: usize,
+}
+
+impl<'a, E: VectorBackend, B: Bitmap> BestEffortCursor<'a, E, B> {
+    pub(crate) fn new(
+        store: &'a VectorStore<E, B>,
+        target: &Vector,
+        search_k: usize,
+        spill: usize,
+    ) -> Result<Self> {
+        let counts = store.candidates(target, spill);
+        let start = counts.top_level(search_k).unwrap_or(0);
+        let mut out = Self {
+            store,
+            target: target.clone(),
+            spill,
+            counts,
+            next_level: Some(start),
+            seen: B::new(),
+            pending: Vec::new(),
+            returned: 0,
+        };
+        out.widen()?;
+        Ok(out)
+    }
+
+    /// The number of candidates scored so far.
+    pub fn scored(&self) -> usize {
+        self.seen.count()
+    }
+
+    /// The number of results handed out so far.
+    pub fn returned(&self) -> usize {
+        self.returned
+    }
+
+    /// Returns up to `n` more results, widening the candidate set as needed.
+    /// An empty page means every reachable candidate has been returned.
+    pub fn next_page(&mut self, n: usize) -> Result<Vec<SearchResult>> {
+        let mut page = Vec::with_capacity(n);
+        while page.len() < n {
+            match self.pending.pop() {
+                Some(r) => page.push(r),
+                None => {
+                    if !self.widen()? {
+                        break;
+                    }
+                }
+            }
+        }
+        self.returned += page.len();
+        Ok(page)
+    }
+
+    /// Scores the next set of candidates, returning false once there is
+    /// nothing left to widen into.
+    pub fn widen(&mut self) -> Result<bool> {
+        loop {
+            let level = match self.next_level {
+                Some(l) => l,
+                None => {
+                    if self.spill + 1 >= self.store.dimensions() {
+                        return Ok(false);
+                    }
+                    self.spill += 1;
+                    self.counts = self.store.candidates(&self.target, self.spill);
+                    self.counts.depth() - 1
+                }
+            };
+            self.next_level = level.checked_sub(1);
+            let mut fresh = self.counts.layer(level).cloned().unwrap_or_default();
+            fresh.and_not(&self.seen);
+            if fresh.is_empty() {
+                continue;
+            }
+            let score = self.store.backend().scorer(&self.target)?;
+            for id in fresh.iter_elems() {
+                let similarity = score(id)?;
+                self.pending.push(SearchResult { similarity, id });
+            }
+            self.seen.or(&fresh);
+            self.pending
+                .sort_by(|a, b| a.similarity.total_cmp(&b.similarity));
+            return Ok(true);
+        }
+    }
+}
+
+impl<E: VectorBackend, B: Bitmap> Iterator for BestEffortCursor<'_, E, B> {
+    type Item = Result<SearchResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_page(1) {
+            Ok(mut page) => page.pop().map(Ok),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
@@ -1,23 +1,37 @@
 use crate::ID;
-use std::ops::{BitOrAssign, BitXorAssign, SubAssign};
+use anyhow::{anyhow, Result};
+use bitvec::field::BitField;
+use std::ops::{BitAndAssign, BitOrAssign, BitXorAssign, SubAssign};
 
 pub use bitvec::prelude::BitVec;
 pub use croaring::Bitmap as CRoaringBitmap;
+pub use croaring::Treemap as CRoaringTreemap;
 pub use roaring::RoaringBitmap;
+pub use roaring::RoaringTreemap;
 
 pub trait Bitmap: std::fmt::Debug + Default + Clone + Send {
+    /// The largest ID this bitmap can hold without truncation.
+    const MAX_ID: ID;
+
     fn new() -> Self;
     fn count(&self) -> usize;
     fn is_empty(&self) -> bool;
     fn add(&mut self, id: ID);
+    fn remove(&mut self, id: ID);
+    fn contains(&self, id: ID) -> bool;
     fn iter_elems(&self) -> impl Iterator<Item = ID>;
     fn and_not(&mut self, rhs: &Self);
+    fn and(&mut self, rhs: &Self);
     fn or(&mut self, rhs: &Self);
     fn xor(&mut self, rhs: &Self);
     fn estimate_size(&self) -> usize;
+    fn to_bytes(&self) -> Result<Vec<u8>>;
+    fn from_bytes(data: &[u8]) -> Result<Self>;
 }
 
 impl Bitmap for roaring::RoaringBitmap {
+    const MAX_ID: ID = u32::MAX as ID;
+
     fn new() -> Self {
         roaring::RoaringBitmap::new()
     }
@@ -31,7 +45,17 @@ impl Bitmap for roaring::RoaringBitmap {
     }
 
     fn add(&mut self, id: ID) {
-        self.insert(id as u32);
+        self.insert(u32::try_from(id).expect("ID out of range for a 32-bit bitmap"));
+    }
+
+    fn remove(&mut self, id: ID) {
+        if let Ok(id) = u32::try_from(id) {
+            self.remove(id);
+        }
+    }
+
+    fn contains(&self, id: ID) -> bool {
+        u32::try_from(id).is_ok_and(|id| self.contains(id))
     }
 
     fn iter_elems(&self) -> impl Iterator<Item = ID> {
@@ -40,6 +64,9 @@ impl Bitmap for roaring::RoaringBitmap {
     fn and_not(&mut self, rhs: &Self) {
         self.sub_assign(rhs)
     }
+    fn and(&mut self, rhs: &Self) {
+        self.bitand_assign(rhs)
+    }
     fn or(&mut self, rhs: &Self) {
         self.bitor_assign(rhs)
     }
@@ -49,9 +76,21 @@ impl Bitmap for roaring::RoaringBitmap {
     fn estimate_size(&self) -> usize {
         self.serialized_size()
     }
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(self.serialized_size());
+        self.serialize_into(&mut out)?;
+        Ok(out)
+    }
+    fn from_bytes(data: &[u8]) -> Result<Self> {
+        Ok(roaring::RoaringBitmap::deserialize_from(data)?)
+    }
 }
 
 impl Bitmap for bitvec::prelude::BitVec {
+    // A bitvec is as long as its largest ID, so cap it where a roaring
+    // bitmap would stop rather than let a hashed ID allocate the world.
+    const MAX_ID: ID = u32::MAX as ID;
+
     fn new() -> Self {
         bitvec::prelude::BitVec::new()
     }
@@ -71,6 +110,16 @@ impl Bitmap for bitvec::prelude::BitVec {
         self.set(id as usize, true)
     }
 
+    fn remove(&mut self, id: ID) {
+        if (id as usize) < self.len() {
+            self.set(id as usize, false)
+        }
+    }
+
+    fn contains(&self, id: ID) -> bool {
+        self.get(id as usize).is_some_and(|b| *b)
+    }
+
     fn iter_elems(&self) -> impl Iterator<Item = ID> {
         self.iter_ones().map(|x| x as ID)
     }
@@ -82,6 +131,16 @@ impl Bitmap for bitvec::prelude::BitVec {
         }
     }
 
+    #[inline]
+    fn and(&mut self, rhs: &Self) {
+        if self.len() > rhs.len() {
+            self.truncate(rhs.len())
+        }
+        for elem in self.as_raw_mut_slice().iter_mut().zip(rhs.as_raw_slice()) {
+            *elem.0 &= elem.1
+        }
+    }
+
     #[inline]
     fn or(&mut self, rhs: &Self) {
         if self.len() < rhs.len() {
@@ -101,9 +160,50 @@ impl Bitmap for bitvec::prelude::BitVec {
     fn estimate_size(&self) -> usize {
         std::mem::size_of_val(self.as_raw_slice())
     }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        // Always 64-bit words, whatever the width of usize on this target.
+        let mut out = Vec::with_capacity(8 + self.len().div_ceil(64) * 8);
+        out.extend_from_slice(&(self.len() as u64).to_le_bytes());
+        for chunk in self.chunks(64) {
+            out.extend_from_slice(&chunk.load_le::<u64>().to_le_bytes());
+        }
+        Ok(out)
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<Self> {
+        if data.len() < 8 || !data.len().is_multiple_of(8) {
+            return Err(anyhow!("Malformed bitvec data"));
+        }
+        let mut words = data
+            .chunks_exact(8)
+            .map(|c| u64::from_le_bytes(c.try_into().unwrap()));
+        let len = words.next().unwrap();
+        // Check the header against the payload before trusting it to size
+        // the allocation.
+        if len > (data.len() / 8 - 1) as u64 * 64 {
+            return Err(anyhow!("Malformed bitvec data"));
+        }
+        let len = len as usize;
+        let mut out = bitvec::prelude::BitVec::with_capacity(len);
+        for word in words {
+            for bit in 0..64 {
+                if out.len() == len {
+                    break;
+                }
+                out.push(word & (1 << bit) != 0);
+            }
+        }
+        if out.len() != len {
+            return Err(anyhow!("Malformed bitvec data"));
+        }
+        Ok(out)
+    }
 }
 
 impl Bitmap for croaring::Bitmap {
+    const MAX_ID: ID = u32::MAX as ID;
+
     fn new() -> Self {
         croaring::Bitmap::new()
     }
@@ -117,7 +217,17 @@ impl Bitmap for croaring::Bitmap {
     }
 
     fn add(&mut self, id: ID) {
-        self.add(id as u32)
+        self.add(u32::try_from(id).expect("ID out of range for a 32-bit bitmap"))
+    }
+
+    fn remove(&mut self, id: ID) {
+        if let Ok(id) = u32::try_from(id) {
+            self.remove(id)
+        }
+    }
+
+    fn contains(&self, id: ID) -> bool {
+        u32::try_from(id).is_ok_and(|id| self.contains(id))
     }
 
     fn iter_elems(&self) -> impl Iterator<Item = ID> {
@@ -128,6 +238,10 @@ impl Bitmap for croaring::Bitmap {
         self.andnot_inplace(rhs)
     }
 
+    fn and(&mut self, rhs: &Self) {
+        self.and_inplace(rhs)
+    }
+
     fn or(&mut self, rhs: &Self) {
         self.or_inplace(rhs)
     }
@@ -139,4 +253,255 @@ impl Bitmap for croaring::Bitmap {
     fn estimate_size(&self) -> usize {
         self.get_serialized_size_in_bytes::<croaring::Native>()
     }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(self.serialize::<croaring::Portable>())
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<Self> {
+        croaring::Bitmap::try_deserialize::<croaring::Portable>(data)
+            .ok_or(anyhow!("Malformed croaring bitmap data"))
+    }
+}
+
+impl Bitmap for roaring::RoaringTreemap {
+    const MAX_ID: ID = ID::MAX;
+
+    fn new() -> Self {
+        roaring::RoaringTreemap::new()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn count(&self) -> usize {
+        self.len() as usize
+    }
+
+    fn add(&mut self, id: ID) {
+        self.insert(id);
+    }
+
+    fn remove(&mut self, id: ID) {
+        self.remove(id);
+    }
+
+    fn contains(&self, id: ID) -> bool {
+        self.contains(id)
+    }
+
+    fn iter_elems(&self) -> impl Iterator<Item = ID> {
+        self.iter()
+    }
+    fn and_not(&mut self, rhs: &Self) {
+        self.sub_assign(rhs)
+    }
+    fn and(&mut self, rhs: &Self) {
+        self.bitand_assign(rhs)
+    }
+    fn or(&mut self, rhs: &Self) {
+        self.bitor_assign(rhs)
+    }
+    fn xor(&mut self, rhs: &Self) {
+        self.bitxor_assign(rhs)
+    }
+    fn estimate_size(&self) -> usize {
+        self.serialized_size()
+    }
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(self.serialized_size());
+        self.serialize_into(&mut out)?;
+        Ok(out)
+    }
+    fn from_bytes(data: &[u8]) -> Result<Self> {
+        Ok(roaring::RoaringTreemap::deserialize_from(data)?)
+    }
+}
+
+impl Bitmap for croaring::Treemap {
+    const MAX_ID: ID = ID::MAX;
+
+    fn new() -> Self {
+        croaring::Treemap::new()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn count(&self) -> usize {
+        self.cardinality() as usize
+    }
+
+    fn add(&mut self, id: ID) {
+        self.add(id)
+    }
+
+    fn remove(&mut self, id: ID) {
+        self.remove(id)
+    }
+
+    fn contains(&self, id: ID) -> bool {
+        self.contains(id)
+    }
+
+    fn iter_elems(&self) -> impl Iterator<Item = ID> {
+        self.iter()
+    }
+
+    fn and_not(&mut self, rhs: &Self) {
+        self.andnot_inplace(rhs)
+    }
+
+    fn and(&mut self, rhs: &Self) {
+        self.and_inplace(rhs)
+    }
+
+    fn or(&mut self, rhs: &Self) {
+        self.or_inplace(rhs)
+    }
+
+    fn xor(&mut self, rhs: &Self) {
+        self.xor_inplace(rhs)
+    }
+
+    fn estimate_size(&self) -> usize {
+        self.get_serialized_size_in_bytes::<croaring::Native>()
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(self.serialize::<croaring::Portable>())
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<Self> {
+        croaring::Treemap::try_deserialize::<croaring::Portable>(data)
+            .ok_or(anyhow!("Malformed croaring treemap data"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BIG_IDS: [ID; 5] = [
+        u32::MAX as ID - 1,
+        u32::MAX as ID,
+        u32::MAX as ID + 1,
+        (1 << 40) + 7,
+        ID::MAX,
+    ];
+
+    fn check_big_ids<B: Bitmap>() {
+        let mut bm = B::new();
+        bm.add(7);
+        for id in BIG_IDS {
+            bm.add(id);
+        }
+        assert_eq!(bm.count(), BIG_IDS.len() + 1);
+        let elems: Vec<ID> = bm.iter_elems().collect();
+        assert_eq!(elems[0], 7);
+        assert_eq!(&elems[1..], &BIG_IDS);
+
+        // 7 and 2^32 + 7 must not alias each other.
+        let mut other = B::new();
+        other.add((1 << 32) + 7);
+        bm.or(&other);
+        assert_eq!(bm.count(), BIG_IDS.len() + 2);
+        bm.and_not(&other);
+        assert_eq!(bm.count(), BIG_IDS.len() + 1);
+        assert!(bm.iter_elems().any(|x| x == 7));
+    }
+
+    fn check_round_trip<B: Bitmap>() {
+        let mut bm = B::new();
+        for id in [0, 3, 64, 65, 1000] {
+            bm.add(id);
+        }
+        bm.remove(64);
+        assert!(!bm.contains(64));
+        assert!(bm.contains(65));
+        let back = B::from_bytes(&bm.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            back.iter_elems().collect::<Vec<_>>(),
+            bm.iter_elems().collect::<Vec<_>>()
+        );
+    }
+
+    fn check_and<B: Bitmap>() {
+        let mut bm = B::new();
+        let mut other = B::new();
+        for id in [1, 5, 64, 300] {
+            bm.add(id);
+        }
+        for id in [5, 6, 300] {
+            other.add(id);
+        }
+        bm.and(&other);
+        assert_eq!(bm.iter_elems().collect::<Vec<_>>(), vec![5, 300]);
+        // A shorter right-hand side clears everything past its end.
+        let mut short = B::new();
+        short.add(5);
+        bm.and(&short);
+        assert_eq!(bm.iter_elems().collect::<Vec<_>>(), vec![5]);
+    }
+
+    #[test]
+    fn intersects() {
+        check_and::<RoaringBitmap>();
+        check_and::<RoaringTreemap>();
+        check_and::<CRoaringBitmap>();
+        check_and::<CRoaringTreemap>();
+        check_and::<BitVec>();
+    }
+
+    #[test]
+    fn round_trips() {
+        check_round_trip::<RoaringBitmap>();
+        check_round_trip::<RoaringTreemap>();
+        check_round_trip::<CRoaringBitmap>();
+        check_round_trip::<CRoaringTreemap>();
+        check_round_trip::<BitVec>();
+    }
+
+    #[test]
+    fn roaring_treemap_big_ids() {
+        check_big_ids::<RoaringTreemap>();
+    }
+
+    #[test]
+    fn croaring_treemap_big_ids() {
+        check_big_ids::<CRoaringTreemap>();
+    }
+
+    #[test]
+    fn bitvec_bytes_use_64_bit_words() {
+        let mut bm = BitVec::new();
+        bm.add(0);
+        bm.add(70);
+        let bytes = bm.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 8 + 2 * 8);
+        assert_eq!(bytes[8..16], 1u64.to_le_bytes());
+        assert_eq!(bytes[16..24], (1u64 << 6).to_le_bytes());
+        assert!(BitVec::from_bytes(&bytes).unwrap().contains(70));
+
+        // A corrupt length is an error, not a huge allocation.
+        let mut corrupt = bytes.clone();
+        corrupt[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(BitVec::from_bytes(&corrupt).is_err());
+        corrupt[..8].copy_from_slice(&129u64.to_le_bytes());
+        assert!(BitVec::from_bytes(&corrupt).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn roaring_bitmap_rejects_big_ids() {
+        RoaringBitmap::new().add(u32::MAX as ID + 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn croaring_bitmap_rejects_big_ids() {
+        Bitmap::add(&mut CRoaringBitmap::new(), u32::MAX as ID + 1);
+    }
 }
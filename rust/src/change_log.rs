@@ -0,0 +1,207 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+use crate::{Basis, Vector, ID};
+
+pub(crate) const LOG_FILE: &str = "changes.jsonl";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ChangeEvent {
+    Put { id: ID, vector: Vector },
+    Delete { id: ID },
+    BasesChanged { bases: Vec<Basis> },
+    // `at` is milliseconds since the epoch.
+    SetExpiry { id: ID, at: u64 },
+}
+
+/// A change event with its position in the log. Sequence numbers start at
+/// 1 and have no gaps.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Change {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub event: ChangeEvent,
+}
+
+/// A durable, ordered log of changes to a store, kept as JSON lines in a
+/// directory, with live subscriptions on top.
+///
+/// Each change is written before it's handed to subscribers; `sync` makes
+/// the writes durable. A torn line left by a crash is dropped on open.
+pub struct ChangeLog {
+    dir: PathBuf,
+    file: File,
+    // The byte offset of each change, indexed by `seq - 1`.
+    offsets: Vec<u64>,
+    len: u64,
+    subscribers: Vec<Sender<Change>>,
+}
+
+impl ChangeLog {
+    pub fn open(dir: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(LOG_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let mut offsets = Vec::new();
+        let mut len = 0;
+        let mut reader = BufReader::new(&file);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let n = reader.read_line(&mut line)?;
+            if n == 0 || !line.ends_with('\n') {
+                break;
+            }
+            offsets.push(len);
+            len += n as u64;
+        }
+        if file.metadata()?.len() != len {
+            file.set_len(len)?;
+        }
+        file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            dir,
+            file,
+            offsets,
+            len,
+            subscribers: Vec::new(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The sequence number of the last change, or 0 if there are none.
+    pub fn last_seq(&self) -> u64 {
+        self.offsets.len() as u64
+    }
+
+    pub fn append(&mut self, event: ChangeEvent) -> Result<u64> {
+        let change = Change {
+            seq: self.last_seq() + 1,
+            event,
+        };
+        let mut line = serde_json::to_vec(&change)?;
+        line.push(b'\n');
+        if let Err(e) = self.file.write_all(&line) {
+            // Don't leave part of a line for the next append to follow.
+            self.file.set_len(self.len)?;
+            return Err(e.into());
+        }
+        self.offsets.push(self.len);
+        self.len += line.len() as u64;
+        self.subscribers.retain(|s| s.send(change.clone()).is_ok());
+        Ok(change.seq)
+    }
+
+    /// Reads every change after `seq`, in order.
+    pub fn changes_since(&self, seq: u64) -> Result<Vec<Change>> {
+        if seq > self.last_seq() {
+            return Err(anyhow!(
+                "Sequence {} is past the end of the log at {}",
+                seq,
+                self.last_seq()
+            ));
+        }
+        let mut reader = BufReader::new(File::open(self.dir.join(LOG_FILE))?);
+        let start = self.offsets.get(seq as usize).copied().unwrap_or(self.len);
+        reader.seek(SeekFrom::Start(start))?;
+        let mut out = Vec::with_capacity((self.last_seq() - seq) as usize);
+        let mut line = String::new();
+        while (out.len() as u64) < self.last_seq() - seq {
+            line.clear();
+            reader.read_line(&mut line)?;
+            out.push(serde_json::from_str(&line)?);
+        }
+        Ok(out)
+    }
+
+    /// Returns a channel that first replays every change after `seq` and
+    /// then receives new ones as they're appended.
+    pub fn subscribe(&mut self, seq: u64) -> Result<Receiver<Change>> {
+        let (tx, rx) = channel();
+        for change in self.changes_since(seq)? {
+            // The receiver can't have gone away yet.
+            tx.send(change).unwrap();
+        }
+        self.subscribers.push(tx);
+        Ok(rx)
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        Ok(self.file.sync_data()?)
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+impl ChangeLog {
+    // Sends every later write to a full disk.
+    pub(crate) fn fill_disk(&mut self) -> Result<()> {
+        self.file = OpenOptions::new().write(true).open("/dev/full")?;
+        Ok(())
+    }
+}
+
+/// Writes changes in the log's JSON lines format, for shipping them to a
+/// follower over a byte stream.
+pub fn write_changes(changes: impl IntoIterator<Item = Change>, w: &mut impl Write) -> Result<()> {
+    for change in changes {
+        serde_json::to_writer(&mut *w, &change)?;
+        w.write_all(b"\n")?;
+    }
+    Ok(w.flush()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn appends_and_reopens() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut log = ChangeLog::open(dir.path().into())?;
+        assert_eq!(log.last_seq(), 0);
+        let rx = log.subscribe(0)?;
+        log.append(ChangeEvent::Put {
+            id: 3,
+            vector: vec![1.0, 0.0],
+        })?;
+        log.append(ChangeEvent::Delete { id: 3 })?;
+        assert_eq!(rx.try_iter().map(|c| c.seq).collect::<Vec<_>>(), vec![1, 2]);
+        drop(rx);
+        assert_eq!(log.append(ChangeEvent::Delete { id: 4 })?, 3);
+        assert!(log.subscribers.is_empty());
+        log.sync()?;
+        drop(log);
+
+        // A torn write at the tail is dropped.
+        let path = dir.path().join(LOG_FILE);
+        let mut f = OpenOptions::new().append(true).open(&path)?;
+        f.write_all(b"{\"seq\":4,\"op\":\"del")?;
+        let mut log = ChangeLog::open(dir.path().into())?;
+        assert_eq!(log.last_seq(), 3);
+        let changes = log.changes_since(1)?;
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].event, ChangeEvent::Delete { id: 3 });
+        assert_eq!(log.append(ChangeEvent::Delete { id: 5 })?, 4);
+        assert_eq!(
+            log.changes_since(3)?[0].event,
+            ChangeEvent::Delete { id: 5 }
+        );
+        assert!(log.changes_since(4)?.is_empty());
+        assert!(log.changes_since(5).is_err());
+        Ok(())
+    }
+}
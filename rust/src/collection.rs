@@ -0,0 +1,323 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use crate::{
+    quantization::Quantization, BF16Quantization, BinaryQuantization, Bitmap, DiskBackend,
+    F16Quantization, Int8Quantization, NoQuantization, ProductQuantization, ResultSet, Vector,
+    VectorBackend, VectorStore, ID,
+};
+
+const CATALOG_FILE: &str = "collections.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CollectionConfig {
+    pub dimensions: usize,
+    pub n_basis: usize,
+    /// The `Quantization::name()` of the quantization to store vectors with.
+    pub quantization: String,
+}
+
+impl CollectionConfig {
+    pub fn new(dimensions: usize, n_basis: usize, quantization: &str) -> Self {
+        Self {
+            dimensions,
+            n_basis,
+            quantization: quantization.into(),
+        }
+    }
+}
+
+/// An open collection. Each variant is a disk-backed store for one of the
+/// supported quantizations.
+pub enum Collection<B: Bitmap> {
+    NoQuantization(VectorStore<DiskBackend<NoQuantization>, B>),
+    BF16(VectorStore<DiskBackend<BF16Quantization>, B>),
+    F16(VectorStore<DiskBackend<F16Quantization>, B>),
+    Int8(VectorStore<DiskBackend<Int8Quantization>, B>),
+    Binary(VectorStore<DiskBackend<BinaryQuantization>, B>),
+}
+
+/// The names of the quantizations a collection can be created with.
+pub fn supported_quantizations() -> [&'static str; 5] {
+    [
+        NoQuantization::default().name(),
+        BF16Quantization::default().name(),
+        F16Quantization::default().name(),
+        Int8Quantization::default().name(),
+        BinaryQuantization::default().name(),
+    ]
+}
+
+macro_rules! dispatch {
+    ($self:expr, $s:ident => $body:expr) => {
+        match $self {
+            Collection::NoQuantization($s) => $body,
+            Collection::BF16($s) => $body,
+            Collection::F16($s) => $body,
+            Collection::Int8($s) => $body,
+            Collection::Binary($s) => $body,
+        }
+    };
+}
+
+impl<B: Bitmap> Collection<B> {
+    fn open(path: PathBuf, config: &CollectionConfig) -> Result<Self> {
+        let (dim, nb) = (config.dimensions, config.n_basis);
+        let out = match config.quantization.as_str() {
+            n if n == NoQuantization::default().name() => Collection::NoQuantization(
+                VectorStore::new_vector_store(DiskBackend::open(path, dim, nb)?)?,
+            ),
+            n if n == BF16Quantization::default().name() => Collection::BF16(
+                VectorStore::new_vector_store(DiskBackend::open(path, dim, nb)?)?,
+            ),
+            n if n == F16Quantization::default().name() => Collection::F16(
+                VectorStore::new_vector_store(DiskBackend::open(path, dim, nb)?)?,
+            ),
+            n if n == Int8Quantization::default().name() => Collection::Int8(
+                VectorStore::new_vector_store(DiskBackend::open(path, dim, nb)?)?,
+            ),
+            n if n == BinaryQuantization::default().name() => Collection::Binary(
+                VectorStore::new_vector_store(DiskBackend::open(path, dim, nb)?)?,
+            ),
+            // The subspace count is part of the type, and the codebooks
+            // need training before the first vector goes in.
+            n if n == ProductQuantization::<1>::default().name() => {
+                return Err(anyhow!(
+                    "Quantization {} is unsupported in collections; use one of {}",
+                    n,
+                    supported_quantizations().join(", ")
+                ))
+            }
+            n => {
+                return Err(anyhow!(
+                    "Unknown quantization {}; use one of {}",
+                    n,
+                    supported_quantizations().join(", ")
+                ))
+            }
+        };
+        Ok(out)
+    }
+
+    pub fn add_vector(&mut self, id: ID, vector: &Vector) -> Result<()> {
+        dispatch!(self, s => s.add_vector(id, vector))
+    }
+
+    pub fn add_vector_iter<'a>(
+        &mut self,
+        iter: impl Iterator<Item = (ID, &'a Vector)>,
+    ) -> Result<()> {
+        dispatch!(self, s => s.add_vector_iter(iter))
+    }
+
+    pub fn remove_vector(&mut self, id: ID) -> Result<()> {
+        dispatch!(self, s => s.remove_vector(id))
+    }
+
+    pub fn find_nearest(
+        &self,
+        target: &Vector,
+        k: usize,
+        search_k: usize,
+        spill: usize,
+    ) -> Result<ResultSet> {
+        dispatch!(self, s => s.find_nearest(target, k, search_k, spill))
+    }
+
+    pub fn full_table_scan(&self, target: &Vector, k: usize) -> Result<ResultSet> {
+        dispatch!(self, s => s.full_table_scan(target, k))
+    }
+
+    pub fn vector_count(&self) -> usize {
+        dispatch!(self, s => s.backend().info().vector_count)
+    }
+
+    /// A rough estimate of the memory this collection keeps resident: its
+    /// index plus its (mapped) vectors.
+    pub fn memory_usage(&self) -> usize {
+        match self {
+            Collection::NoQuantization(s) => memory_usage(s),
+            Collection::BF16(s) => memory_usage(s),
+            Collection::F16(s) => memory_usage(s),
+            Collection::Int8(s) => memory_usage(s),
+            Collection::Binary(s) => memory_usage(s),
+        }
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        dispatch!(self, s => s.sync())
+    }
+
+    pub fn close(self) -> Result<()> {
+        dispatch!(self, s => s.close())
+    }
+}
+
+fn memory_usage<Q: Quantization, B: Bitmap>(s: &VectorStore<DiskBackend<Q>, B>) -> usize {
+    let info = s.backend().info();
+    s.index_size() + info.vector_count * s.backend().quantizer().vector_size(info.dimensions)
+}
+
+struct OpenCollection<B: Bitmap> {
+    collection: Collection<B>,
+    last_used: u64,
+}
+
+/// Hosts many named collections under one root directory, keeping open
+/// only as many as fit in the memory budget.
+pub struct CollectionManager<B: Bitmap = crate::CRoaringTreemap> {
+    root: PathBuf,
+    memory_budget: usize,
+    catalog: BTreeMap<String, CollectionConfig>,
+    open: HashMap<String, OpenCollection<B>>,
+    clock: u64,
+}
+
+impl<B: Bitmap> CollectionManager<B> {
+    pub fn open(root: PathBuf, memory_budget: usize) -> Result<Self> {
+        std::fs::create_dir_all(&root)?;
+        let catalog_path = root.join(CATALOG_FILE);
+        let catalog = if catalog_path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(catalog_path)?)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self {
+            root,
+            memory_budget,
+            catalog,
+            open: HashMap::new(),
+            clock: 0,
+        })
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &str> {
+        self.catalog.keys().map(|k| k.as_str())
+    }
+
+    pub fn config(&self, name: &str) -> Option<&CollectionConfig> {
+        self.catalog.get(name)
+    }
+
+    pub fn open_count(&self) -> usize {
+        self.open.len()
+    }
+
+    pub fn create(&mut self, name: &str, config: CollectionConfig) -> Result<&mut Collection<B>> {
+        check_name(name)?;
+        if self.catalog.contains_key(name) {
+            return Err(anyhow!("Collection {} already exists", name));
+        }
+        let path = self.root.join(name);
+        if path.exists() {
+            return Err(anyhow!("Collection directory {} already exists", name));
+        }
+        let collection = Collection::open(path, &config)?;
+        self.catalog.insert(name.into(), config);
+        self.save_catalog()?;
+        self.insert_open(name, collection)
+    }
+
+    /// Opens the named collection if it isn't open already.
+    pub fn get(&mut self, name: &str) -> Result<&mut Collection<B>> {
+        if self.open.contains_key(name) {
+            self.clock += 1;
+            let entry = self.open.get_mut(name).unwrap();
+            entry.last_used = self.clock;
+            // Writes since the last call may have pushed us over budget.
+            self.evict(name)?;
+            return Ok(&mut self.open.get_mut(name).unwrap().collection);
+        }
+        let config = self
+            .catalog
+            .get(name)
+            .ok_or(anyhow!("No collection named {}", name))?;
+        let collection = Collection::open(self.root.join(name), config)?;
+        self.insert_open(name, collection)
+    }
+
+    pub fn drop_collection(&mut self, name: &str) -> Result<()> {
+        if self.catalog.remove(name).is_none() {
+            return Err(anyhow!("No collection named {}", name));
+        }
+        self.save_catalog()?;
+        // Nothing to flush; the files are about to go.
+        self.open.remove(name);
+        std::fs::remove_dir_all(self.root.join(name))?;
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        for entry in self.open.values_mut() {
+            entry.collection.sync()?;
+        }
+        self.save_catalog()
+    }
+
+    pub fn close(mut self) -> Result<()> {
+        for (_, entry) in self.open.drain() {
+            entry.collection.close()?;
+        }
+        self.save_catalog()
+    }
+
+    fn insert_open(&mut self, name: &str, collection: Collection<B>) -> Result<&mut Collection<B>> {
+        self.clock += 1;
+        self.open.insert(
+            name.into(),
+            OpenCollection {
+                collection,
+                last_used: self.clock,
+            },
+        );
+        self.evict(name)?;
+        Ok(&mut self.open.get_mut(name).unwrap().collection)
+    }
+
+    /// Closes least-recently-used collections other than `keep` until the
+    /// open set fits in the budget.
+    fn evict(&mut self, keep: &str) -> Result<()> {
+        loop {
+            let used: usize = self
+                .open
+                .values()
+                .map(|e| e.collection.memory_usage())
+                .sum();
+            if used <= self.memory_budget {
+                return Ok(());
+            }
+            let victim = self
+                .open
+                .iter()
+                .filter(|(k, _)| k.as_str() != keep)
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone());
+            match victim {
+                Some(v) => self.open.remove(&v).unwrap().collection.close()?,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    fn save_catalog(&self) -> Result<()> {
+        Ok(serde_json::to_writer(
+            &std::fs::File::create(self.root.join(CATALOG_FILE))?,
+            &self.catalog,
+        )?)
+    }
+}
+
+fn check_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(anyhow!("Invalid collection name {:?}", name));
+    }
+    Ok(())
+}
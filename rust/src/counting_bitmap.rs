@@ -37,7 +37,24 @@ impl<B: Bitmap> CountingBitmap<B> {
     }
 
     pub fn top_k(&self, search_k: usize) -> Option<&B> {
-        self.bitmaps.iter().rev().find(|x| x.count() >= search_k)
+        self.top_level(search_k).map(|l| &self.bitmaps[l])
+    }
+
+    /// The highest counting layer holding at least `search_k` IDs.
+    pub fn top_level(&self, search_k: usize) -> Option<usize> {
+        (0..self.bitmaps.len())
+            .rev()
+            .find(|l| self.bitmaps[*l].count() >= search_k)
+    }
+
+    /// The number of counting layers.
+    pub fn depth(&self) -> usize {
+        self.bitmaps.len()
+    }
+
+    /// The IDs seen at least `level + 1` times.
+    pub fn layer(&self, level: usize) -> Option<&B> {
+        self.bitmaps.get(level)
     }
 }
 
@@ -0,0 +1,109 @@
+use anyhow::Result;
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{Bitmap, ID};
+
+/// Tracks when vectors with a time-to-live expire.
+#[derive(Default, Clone)]
+pub(crate) struct ExpiryMap {
+    by_id: HashMap<ID, SystemTime>,
+    by_time: BTreeSet<(SystemTime, ID)>,
+}
+
+impl ExpiryMap {
+    pub fn is_empty(&self) -> bool {
+        self.by_id.is_empty()
+    }
+
+    pub fn get(&self, id: ID) -> Option<SystemTime> {
+        self.by_id.get(&id).copied()
+    }
+
+    pub fn set(&mut self, id: ID, at: SystemTime) {
+        self.clear(id);
+        self.by_id.insert(id, at);
+        self.by_time.insert((at, id));
+    }
+
+    /// Forgets the expiry for `id`, returning whether it had one.
+    pub fn clear(&mut self, id: ID) -> bool {
+        match self.by_id.remove(&id) {
+            Some(at) => self.by_time.remove(&(at, id)),
+            None => false,
+        }
+    }
+
+    /// The IDs whose expiry is at or before `now`.
+    pub fn expired(&self, now: SystemTime) -> impl Iterator<Item = ID> + '_ {
+        self.by_time
+            .iter()
+            .take_while(move |(at, _)| *at <= now)
+            .map(|(_, id)| *id)
+    }
+
+    pub fn expired_bitmap<B: Bitmap>(&self, now: SystemTime) -> B {
+        let mut out = B::new();
+        for id in self.expired(now) {
+            out.add(id);
+        }
+        out
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ID, SystemTime)> + '_ {
+        self.by_id.iter().map(|(id, at)| (*id, *at))
+    }
+
+    // Stored as milliseconds since the epoch.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let millis: HashMap<ID, u64> = self.iter().map(|(id, at)| (id, to_millis(at))).collect();
+        Ok(serde_json::to_vec(&millis)?)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let millis: HashMap<ID, u64> = serde_json::from_slice(data)?;
+        let mut out = Self::default();
+        for (id, ms) in millis {
+            out.set(id, from_millis(ms));
+        }
+        Ok(out)
+    }
+}
+
+/// Expiry times as stored and logged: milliseconds since the epoch.
+pub(crate) fn to_millis(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+pub(crate) fn from_millis(ms: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(ms)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn expires_in_order() {
+        let now = SystemTime::now();
+        let mut em = ExpiryMap::default();
+        em.set(1, now + Duration::from_secs(60));
+        em.set(2, now - Duration::from_secs(60));
+        em.set(3, now);
+        assert_eq!(em.expired(now).collect::<Vec<_>>(), vec![2, 3]);
+        em.set(2, now + Duration::from_secs(120));
+        assert_eq!(em.expired(now).collect::<Vec<_>>(), vec![3]);
+        assert!(em.clear(3));
+        assert!(!em.clear(3));
+        assert_eq!(em.expired(now).count(), 0);
+
+        let back = ExpiryMap::from_bytes(&em.to_bytes().unwrap()).unwrap();
+        let later = now + Duration::from_secs(90);
+        assert_eq!(back.expired(later).collect::<Vec<_>>(), vec![1]);
+        assert!(back.get(2).is_some());
+    }
+}
@@ -0,0 +1,299 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::PathBuf,
+};
+
+use crate::{
+    change_log::LOG_FILE, expiry::from_millis, Bitmap, Change, ChangeEvent, VectorBackend,
+    VectorStore,
+};
+
+const APPLIED_BLOB: &str = "replica_seq";
+
+/// Where a follower reads the leader's changes from.
+pub trait LogSource {
+    /// Returns whatever complete changes have arrived since the last call,
+    /// in order. An empty batch means nothing new, for now.
+    fn poll(&mut self) -> Result<Vec<Change>>;
+
+    /// The sequence number of the leader's last change, if the source can
+    /// tell without handing anything over.
+    fn leader_seq(&mut self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+}
+
+// Parses the whole JSON lines at the front of `data`, returning them and
+// how many bytes they took. A trailing partial line is left alone.
+fn parse_lines(data: &[u8]) -> Result<(Vec<Change>, usize)> {
+    let Some(end) = data.iter().rposition(|b| *b == b'\n') else {
+        return Ok((Vec::new(), 0));
+    };
+    let changes = data[..end]
+        .split(|b| *b == b'\n')
+        .filter(|l| !l.is_empty())
+        .map(|l| Ok(serde_json::from_slice(l)?))
+        .collect::<Result<_>>()?;
+    Ok((changes, end + 1))
+}
+
+// Splits bytes into JSON lines, holding on to a trailing partial line
+// until the rest of it arrives.
+#[derive(Default)]
+struct LineBuffer {
+    partial: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn push(&mut self, data: &[u8]) -> Result<Vec<Change>> {
+        self.partial.extend_from_slice(data);
+        // Lines that fail to parse stay buffered, so nothing is lost.
+        let (changes, used) = parse_lines(&self.partial)?;
+        self.partial.drain(..used);
+        Ok(changes)
+    }
+}
+
+// Read backwards from the end of the log this many bytes at a time.
+const TAIL_CHUNK: u64 = 4096;
+
+// Just the sequence number of a change line, skipping the rest.
+#[derive(Deserialize)]
+struct LineSeq {
+    seq: u64,
+}
+
+/// Tails the change log a leader writes with `VectorStore::open_change_log`,
+/// e.g. in a directory shipped or shared from the leader's machine.
+pub struct DirectorySource {
+    path: PathBuf,
+    // The end of the last line handed over.
+    offset: u64,
+}
+
+impl DirectorySource {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            path: dir.join(LOG_FILE),
+            offset: 0,
+        }
+    }
+}
+
+impl LogSource for DirectorySource {
+    fn poll(&mut self) -> Result<Vec<Change>> {
+        // The leader may not have written anything yet.
+        let Ok(mut file) = File::open(&self.path) else {
+            return Ok(Vec::new());
+        };
+        let len = file.metadata()?.len();
+        if len < self.offset {
+            return Err(anyhow!(
+                "Change log shrank to {} bytes, below the {} already read",
+                len,
+                self.offset
+            ));
+        }
+        // A partial line is read again next time, in case the leader
+        // drops it on reopening rather than finishing it.
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        let (changes, used) = parse_lines(&data)?;
+        self.offset += used as u64;
+        Ok(changes)
+    }
+
+    fn leader_seq(&mut self) -> Result<Option<u64>> {
+        let Ok(mut file) = File::open(&self.path) else {
+            return Ok(Some(0));
+        };
+        // Grow the tail backwards until it holds the last whole line.
+        let mut start = file.metadata()?.len();
+        let mut tail = Vec::new();
+        loop {
+            if let Some(end) = tail.iter().rposition(|b| *b == b'\n') {
+                let begin = tail[..end].iter().rposition(|b| *b == b'\n');
+                if begin.is_some() || start == 0 {
+                    let line = &tail[begin.map_or(0, |b| b + 1)..end];
+                    return Ok(Some(serde_json::from_slice::<LineSeq>(line)?.seq));
+                }
+            } else if start == 0 {
+                return Ok(Some(0));
+            }
+            let next = start.saturating_sub(TAIL_CHUNK);
+            let mut chunk = vec![0; (start - next) as usize];
+            file.seek(SeekFrom::Start(next))?;
+            file.read_exact(&mut chunk)?;
+            chunk.append(&mut tail);
+            tail = chunk;
+            start = next;
+        }
+    }
+}
+
+/// Reads changes written with `write_changes` from a byte stream, such as a
+/// socket or pipe. Each poll blocks until at least one whole change
+/// arrives; at the end of the stream it returns nothing.
+pub struct StreamSource<R: Read> {
+    reader: R,
+    buf: Vec<u8>,
+    lines: LineBuffer,
+}
+
+impl<R: Read> StreamSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: vec![0; 64 * 1024],
+            lines: LineBuffer::default(),
+        }
+    }
+}
+
+impl<R: Read> LogSource for StreamSource<R> {
+    fn poll(&mut self) -> Result<Vec<Change>> {
+        loop {
+            let n = self.reader.read(&mut self.buf)?;
+            if n == 0 {
+                return Ok(Vec::new());
+            }
+            let changes = self.lines.push(&self.buf[..n])?;
+            if !changes.is_empty() {
+                return Ok(changes);
+            }
+        }
+    }
+}
+
+/// A warm standby that applies a leader's changes to its own store.
+///
+/// The follower remembers the last sequence number it applied, persisted
+/// with the store on `sync`, so it resumes where it left off and skips
+/// anything it has already seen.
+pub struct Follower<E: VectorBackend, B: Bitmap, S: LogSource> {
+    store: VectorStore<E, B>,
+    source: S,
+    applied: u64,
+    received: u64,
+    pending: VecDeque<Change>,
+}
+
+impl<E: VectorBackend, B: Bitmap, S: LogSource> Follower<E, B, S> {
+    pub fn new(store: VectorStore<E, B>, source: S) -> Result<Self> {
+        let applied = match store.backend().load_blob(APPLIED_BLOB)? {
+            Some(data) => u64::from_le_bytes(
+                data.try_into()
+                    .map_err(|_| anyhow!("Bad replica sequence blob"))?,
+            ),
+            None => 0,
+        };
+        Ok(Self {
+            store,
+            source,
+            applied,
+            received: applied,
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// The store, for serving reads from the standby.
+    pub fn store(&self) -> &VectorStore<E, B> {
+        &self.store
+    }
+
+    /// The sequence number of the last change applied.
+    pub fn applied_seq(&self) -> u64 {
+        self.applied
+    }
+
+    /// How many changes the follower has received but not yet applied.
+    /// This isn't how far it is behind the leader: see `lag` for that.
+    pub fn unapplied(&self) -> u64 {
+        self.received - self.applied
+    }
+
+    /// How many of the leader's changes haven't been applied yet, or
+    /// `None` if the source can't see the leader's position.
+    pub fn lag(&mut self) -> Result<Option<u64>> {
+        Ok(self
+            .source
+            .leader_seq()?
+            .map(|seq| seq.saturating_sub(self.applied)))
+    }
+
+    /// Pulls new changes from the source without applying them, returning
+    /// how many arrived.
+    pub fn fetch(&mut self) -> Result<usize> {
+        let mut n = 0;
+        for change in self.source.poll()? {
+            if change.seq <= self.received {
+                continue;
+            }
+            if change.seq != self.received + 1 {
+                return Err(anyhow!(
+                    "Missing changes {} to {}",
+                    self.received + 1,
+                    change.seq - 1
+                ));
+            }
+            self.received = change.seq;
+            self.pending.push_back(change);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    /// Applies up to `max` received changes, returning how many. A change
+    /// that fails stays pending, to be retried by the next call.
+    pub fn apply(&mut self, max: usize) -> Result<usize> {
+        let mut n = 0;
+        while n < max {
+            let Some(change) = self.pending.front() else {
+                break;
+            };
+            match &change.event {
+                ChangeEvent::Put { id, vector } => self.store.add_vector(*id, vector)?,
+                ChangeEvent::Delete { id } => self.store.remove_vector(*id)?,
+                ChangeEvent::BasesChanged { bases } => self.store.set_bases(bases.clone())?,
+                ChangeEvent::SetExpiry { id, at } => {
+                    self.store.set_expiry(*id, from_millis(*at))?
+                }
+            }
+            self.applied = change.seq;
+            self.pending.pop_front();
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    /// Fetches and applies everything available, returning how many
+    /// changes were applied.
+    pub fn catch_up(&mut self) -> Result<usize> {
+        let mut total = 0;
+        while self.fetch()? > 0 || !self.pending.is_empty() {
+            total += self.apply(usize::MAX)?;
+        }
+        Ok(total)
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        self.store
+            .backend_mut()
+            .save_blob(APPLIED_BLOB, &self.applied.to_le_bytes())?;
+        self.store.sync()
+    }
+
+    /// Stops following and hands back the store to take writes, after
+    /// applying everything the source has. Open a change log on it to make
+    /// it a leader in turn.
+    pub fn promote(mut self) -> Result<VectorStore<E, B>> {
+        self.catch_up()?;
+        self.sync()?;
+        Ok(self.store)
+    }
+}
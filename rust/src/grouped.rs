@@ -0,0 +1,77 @@
+use anyhow::Result;
+
+use crate::{result::SearchResult, BestEffortCursor, Bitmap, VectorBackend, ID};
+
+/// The best hits sharing one group key, best first.
+#[derive(Debug, Clone)]
+pub struct Group<K> {
+    pub key: K,
+    pub hits: Vec<SearchResult>,
+}
+
+impl<K> Group<K> {
+    /// The similarity of the group's best hit, which ranks the group.
+    pub fn similarity(&self) -> f32 {
+        self.hits[0].similarity
+    }
+}
+
+/// Pulls pages of `page` results off the cursor, keeping the best
+/// `per_group` hits of each key, until `groups` distinct keys have turned
+/// up and the latest page had nothing better than the `groups`-th best
+/// group, or the cursor runs dry. IDs without a key are skipped.
+pub(crate) fn collect_groups<E: VectorBackend, B: Bitmap, K: PartialEq>(
+    mut cursor: BestEffortCursor<'_, E, B>,
+    groups: usize,
+    per_group: usize,
+    page: usize,
+    key: impl Fn(ID) -> Option<K>,
+) -> Result<Vec<Group<K>>> {
+    let mut out: Vec<Group<K>> = Vec::new();
+    loop {
+        let results = cursor.next_page(page)?;
+        if results.is_empty() {
+            break;
+        }
+        // Pages aren't in global order, so a wider layer can still turn up
+        // a better group once there are enough; stop when one doesn't.
+        let best = results
+            .iter()
+            .map(|r| r.similarity)
+            .fold(f32::MIN, f32::max);
+        let done = nth_best(&out, groups).is_some_and(|nth| best <= nth);
+        for r in results {
+            let Some(k) = key(r.id) else {
+                continue;
+            };
+            match out.iter_mut().find(|g| g.key == k) {
+                Some(g) => {
+                    // A wider layer can turn up a better hit than one
+                    // already kept, so insert in order.
+                    let at = g.hits.partition_point(|h| h.similarity >= r.similarity);
+                    if at < per_group {
+                        g.hits.insert(at, r);
+                        g.hits.truncate(per_group);
+                    }
+                }
+                None => out.push(Group {
+                    key: k,
+                    hits: vec![r],
+                }),
+            }
+        }
+        if done {
+            break;
+        }
+    }
+    out.sort_by(|a, b| b.similarity().total_cmp(&a.similarity()));
+    out.truncate(groups);
+    Ok(out)
+}
+
+// The best similarity of the n-th best group, if there are that many.
+fn nth_best<K>(out: &[Group<K>], n: usize) -> Option<f32> {
+    let mut best: Vec<f32> = out.iter().map(|g| g.similarity()).collect();
+    best.sort_by(|a, b| b.total_cmp(a));
+    best.get(n.checked_sub(1)?).copied()
+}
@@ -0,0 +1,280 @@
+use anyhow::{anyhow, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashMap, hash::Hash};
+
+use crate::{Bitmap, ResultSet, Vector, VectorBackend, VectorStore, ID};
+
+const KEY_MAP_BLOB: &str = "key_map";
+
+/// Assigns dense internal IDs to arbitrary external keys, reusing the IDs
+/// of removed keys first.
+#[derive(Debug, Default)]
+pub struct KeyMap<K> {
+    to_id: HashMap<K, ID>,
+    keys: Vec<Option<K>>,
+    free: Vec<ID>,
+}
+
+impl<K: Hash + Eq + Clone> KeyMap<K> {
+    pub fn new() -> Self {
+        Self {
+            to_id: HashMap::new(),
+            keys: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.to_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to_id.is_empty()
+    }
+
+    pub fn get_id(&self, key: &K) -> Option<ID> {
+        self.to_id.get(key).copied()
+    }
+
+    pub fn get_key(&self, id: ID) -> Option<&K> {
+        self.keys.get(id as usize).and_then(|k| k.as_ref())
+    }
+
+    /// Returns the ID for `key`, assigning a new one if it isn't mapped yet.
+    pub fn assign(&mut self, key: K) -> ID {
+        if let Some(id) = self.to_id.get(&key) {
+            return *id;
+        }
+        let id = match self.free.pop() {
+            Some(id) => id,
+            None => {
+                self.keys.push(None);
+                (self.keys.len() - 1) as ID
+            }
+        };
+        self.keys[id as usize] = Some(key.clone());
+        self.to_id.insert(key, id);
+        id
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<ID> {
+        let id = self.to_id.remove(key)?;
+        self.keys[id as usize] = None;
+        self.free.push(id);
+        Some(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, ID)> {
+        self.keys
+            .iter()
+            .enumerate()
+            .filter_map(|(id, k)| k.as_ref().map(|k| (k, id as ID)))
+    }
+}
+
+impl<K: Hash + Eq + Clone + Serialize + DeserializeOwned> KeyMap<K> {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&self.keys)?)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let keys: Vec<Option<K>> = serde_json::from_slice(data)?;
+        let mut out = Self::new();
+        for (id, key) in keys.iter().enumerate() {
+            match key {
+                Some(k) => {
+                    if out.to_id.insert(k.clone(), id as ID).is_some() {
+                        return Err(anyhow!("Duplicate key in key map"));
+                    }
+                }
+                None => out.free.push(id as ID),
+            }
+        }
+        // Hand out the lowest free IDs first.
+        out.free.reverse();
+        out.keys = keys;
+        Ok(out)
+    }
+}
+
+#[derive(Debug)]
+pub struct KeyedSearchResult<K> {
+    pub similarity: f32,
+    pub key: K,
+}
+
+/// A `VectorStore` addressed by external keys instead of IDs. The mapping is
+/// saved through the backend alongside the index on `sync`.
+pub struct KeyedVectorStore<K, E: VectorBackend, B: Bitmap> {
+    store: VectorStore<E, B>,
+    keys: KeyMap<K>,
+}
+
+impl<K, E, B> KeyedVectorStore<K, E, B>
+where
+    K: Hash + Eq + Clone + Serialize + DeserializeOwned,
+    E: VectorBackend,
+    B: Bitmap,
+{
+    pub fn new(store: VectorStore<E, B>) -> Result<Self> {
+        let keys = match store.backend().load_blob(KEY_MAP_BLOB)? {
+            Some(data) => KeyMap::from_bytes(&data)?,
+            None => KeyMap::new(),
+        };
+        Ok(Self { store, keys })
+    }
+
+    pub fn store(&self) -> &VectorStore<E, B> {
+        &self.store
+    }
+
+    pub fn key_map(&self) -> &KeyMap<K> {
+        &self.keys
+    }
+
+    pub fn add_vector(&mut self, key: K, vector: &Vector) -> Result<ID> {
+        let is_new = self.keys.get_id(&key).is_none();
+        let id = self.keys.assign(key.clone());
+        if let Err(e) = self.store.add_vector(id, vector) {
+            if is_new {
+                self.keys.remove(&key);
+            }
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    pub fn add_vector_iter<'a>(
+        &mut self,
+        iter: impl Iterator<Item = (K, &'a Vector)>,
+    ) -> Result<()> {
+        let keys = &mut self.keys;
+        let mut fresh = Vec::new();
+        let res = self.store.add_vector_iter(iter.map(|(key, v)| {
+            if keys.get_id(&key).is_none() {
+                fresh.push(key.clone());
+            }
+            (keys.assign(key), v)
+        }));
+        if let Err(e) = res {
+            // Vectors before the failure were stored; unmap the new keys
+            // that never got one.
+            for key in fresh {
+                if let Some(id) = self.keys.get_id(&key) {
+                    if !self.store.backend().vector_exists(id) {
+                        self.keys.remove(&key);
+                    }
+                }
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Removes the vector stored under `key`, returning whether it existed.
+    pub fn remove_vector(&mut self, key: &K) -> Result<bool> {
+        match self.keys.get_id(key) {
+            Some(id) => {
+                // Keep the key until the vector is gone, so a failed removal
+                // can still be retried through it.
+                self.store.remove_vector(id)?;
+                self.keys.remove(key);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn find_nearest(
+        &self,
+        target: &Vector,
+        k: usize,
+        search_k: usize,
+        spill: usize,
+    ) -> Result<Vec<KeyedSearchResult<K>>> {
+        let rs = self.store.find_nearest(target, k, search_k, spill)?;
+        self.translate(&rs)
+    }
+
+    pub fn full_table_scan(&self, target: &Vector, k: usize) -> Result<Vec<KeyedSearchResult<K>>> {
+        let rs = self.store.full_table_scan(target, k)?;
+        self.translate(&rs)
+    }
+
+    fn translate(&self, rs: &ResultSet) -> Result<Vec<KeyedSearchResult<K>>> {
+        rs.iter_results()
+            .map(|r| {
+                let key = self
+                    .keys
+                    .get_key(r.id)
+                    .ok_or(anyhow!("No key mapped for ID {}", r.id))?;
+                Ok(KeyedSearchResult {
+                    similarity: r.similarity,
+                    key: key.clone(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        let data = self.keys.to_bytes()?;
+        self.store.backend_mut().save_blob(KEY_MAP_BLOB, &data)?;
+        self.store.sync()
+    }
+
+    pub fn close(mut self) -> Result<()> {
+        self.sync()?;
+        self.store.close()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reuses_freed_ids() {
+        let mut km = KeyMap::<String>::new();
+        assert_eq!(km.assign("a".into()), 0);
+        assert_eq!(km.assign("b".into()), 1);
+        assert_eq!(km.assign("c".into()), 2);
+        assert_eq!(km.assign("a".into()), 0);
+        assert_eq!(km.remove(&"b".into()), Some(1));
+        assert_eq!(km.get_key(1), None);
+        assert_eq!(km.assign("d".into()), 1);
+        assert_eq!(km.len(), 3);
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut km = KeyMap::<String>::new();
+        for k in ["a", "b", "c", "d"] {
+            km.assign(k.into());
+        }
+        km.remove(&"a".into());
+        km.remove(&"c".into());
+        let back = KeyMap::<String>::from_bytes(&km.to_bytes().unwrap()).unwrap();
+        assert_eq!(back.get_id(&"b".into()), Some(1));
+        assert_eq!(back.get_key(3), Some(&"d".to_string()));
+        assert_eq!(back.len(), 2);
+        let mut back = back;
+        assert_eq!(back.assign("e".into()), 0);
+        assert_eq!(back.assign("f".into()), 2);
+        assert_eq!(back.assign("g".into()), 4);
+    }
+
+    #[test]
+    fn failed_batch_unmaps_new_keys() {
+        let mem = crate::MemoryBackend::new(2, 1).unwrap();
+        let mut store = KeyedVectorStore::new(VectorStore::new(mem).unwrap()).unwrap();
+        let (good, bad) = (vec![1.0, 0.0], vec![1.0, 0.0, 0.0]);
+        let batch = [("a", &good), ("b", &good), ("c", &bad), ("d", &good)];
+        assert!(store
+            .add_vector_iter(batch.iter().map(|(k, v)| (k.to_string(), *v)))
+            .is_err());
+        assert_eq!(store.key_map().get_id(&"b".into()), Some(1));
+        assert_eq!(store.key_map().get_id(&"c".into()), None);
+        assert_eq!(store.key_map().len(), 2);
+        assert_eq!(store.add_vector("e".into(), &good).unwrap(), 2);
+    }
+}
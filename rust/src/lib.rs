@@ -1,10 +1,14 @@
 pub(crate) mod counting_bitmap;
 pub(crate) mod quantization;
 pub use quantization::BF16Quantization;
+pub use quantization::BinaryQuantization;
+pub use quantization::F16Quantization;
 pub use quantization::NoQuantization;
+pub use quantization::Quantization;
+pub use quantization::{Calibration, Int8Quantization, ProductQuantization};
 
 pub mod result;
-pub use result::ResultSet;
+pub use result::{ResultSet, SearchResult};
 
 pub(crate) mod spaces;
 pub(crate) mod unaligned_f32;
@@ -12,19 +16,64 @@ pub(crate) mod unaligned_f32;
 pub mod backend;
 pub use backend::VectorBackend;
 
+pub(crate) mod slot_map;
+
 pub(crate) mod backend_memory;
 pub use backend_memory::MemoryBackend;
 pub use backend_memory::QuantizedMemoryBackend;
 
-//pub(crate) mod vector_file;
-//pub(crate) mod backend_disk;
-//pub use backend_disk::DiskBackend;
+pub(crate) mod backend_two_stage;
+pub use backend_two_stage::TwoStageBackend;
+
+pub(crate) mod backend_disk;
+pub(crate) mod vector_file;
+pub use backend_disk::{DiskBackend, DiskSnapshot};
 
 pub mod vector;
 
 pub(crate) mod vector_store;
 pub use vector_store::VectorStore;
 
+pub(crate) mod expiry;
+
+pub(crate) mod change_log;
+pub use change_log::{write_changes, Change, ChangeEvent, ChangeLog};
+
+pub(crate) mod follower;
+pub use follower::{DirectorySource, Follower, LogSource, StreamSource};
+
+pub(crate) mod snapshot;
+pub use snapshot::{Snapshot, SnapshotBackend};
+
+pub(crate) mod query;
+pub use query::Query;
+
+pub(crate) mod payload;
+pub use payload::{Filter, Payload, PayloadStore, Value};
+
+pub(crate) mod search_options;
+pub use search_options::{CancellationToken, SearchOptions};
+
+pub(crate) mod best_effort_cursor;
+pub use best_effort_cursor::BestEffortCursor;
+
+pub(crate) mod grouped;
+pub use grouped::Group;
+
+#[cfg(feature = "async")]
+pub(crate) mod async_store;
+#[cfg(feature = "async")]
+pub use async_store::AsyncVectorStore;
+
+pub(crate) mod sharded_store;
+pub use sharded_store::{ShardRouting, ShardedStore};
+
+pub(crate) mod collection;
+pub use collection::{supported_quantizations, Collection, CollectionConfig, CollectionManager};
+
+pub(crate) mod key_map;
+pub use key_map::{KeyMap, KeyedSearchResult, KeyedVectorStore};
+
 mod helpers;
 pub use helpers::*;
 
@@ -0,0 +1,460 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    ops::{Bound, RangeBounds},
+};
+
+use crate::{Bitmap, ID};
+
+/// A typed payload field.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    /// A set of strings; equality matches any one of them.
+    Tags(Vec<String>),
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Int(value as i64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Str(value.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Str(value)
+    }
+}
+
+impl From<Vec<String>> for Value {
+    fn from(value: Vec<String>) -> Self {
+        Value::Tags(value)
+    }
+}
+
+pub type Payload = BTreeMap<String, Value>;
+
+/// A boolean expression over payload fields.
+#[derive(Clone, Debug)]
+pub enum Filter {
+    Eq(String, Value),
+    Range(String, Bound<Value>, Bound<Value>),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    pub fn eq(field: &str, value: impl Into<Value>) -> Self {
+        Filter::Eq(field.into(), value.into())
+    }
+
+    pub fn range(field: &str, range: impl RangeBounds<Value>) -> Self {
+        Filter::Range(
+            field.into(),
+            range.start_bound().cloned(),
+            range.end_bound().cloned(),
+        )
+    }
+
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Filter::And(mut fs) => {
+                fs.push(other);
+                Filter::And(fs)
+            }
+            f => Filter::And(vec![f, other]),
+        }
+    }
+
+    pub fn or(self, other: Filter) -> Self {
+        match self {
+            Filter::Or(mut fs) => {
+                fs.push(other);
+                Filter::Or(fs)
+            }
+            f => Filter::Or(vec![f, other]),
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Self {
+        Filter::Not(Box::new(self))
+    }
+}
+
+// Numbers share one ordering so that integer and float fields can be
+// compared with either kind of bound. Integers beyond 2^53 lose precision.
+#[derive(Clone, Copy, Debug)]
+struct Num(f64);
+
+impl PartialEq for Num {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Num {}
+
+impl PartialOrd for Num {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Num {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+// The variant order matters: range bounds on one kind are clamped to that
+// kind's neighbors so a range never spills into another kind.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum IndexKey {
+    Bool(bool),
+    Num(Num),
+    Str(String),
+}
+
+fn index_keys(value: &Value) -> Vec<IndexKey> {
+    match value {
+        Value::Bool(b) => vec![IndexKey::Bool(*b)],
+        Value::Int(i) => vec![IndexKey::Num(Num(*i as f64))],
+        Value::Float(f) => vec![IndexKey::Num(Num(*f))],
+        Value::Str(s) => vec![IndexKey::Str(s.clone())],
+        Value::Tags(ts) => ts.iter().map(|t| IndexKey::Str(t.clone())).collect(),
+    }
+}
+
+fn bound_key(bound: &Bound<Value>) -> Result<Bound<IndexKey>> {
+    let key = |v: &Value| match v {
+        Value::Int(_) | Value::Float(_) | Value::Str(_) => Ok(index_keys(v).remove(0)),
+        _ => Err(anyhow!(
+            "Range bounds must be numbers or strings, not {:?}",
+            v
+        )),
+    };
+    Ok(match bound {
+        Bound::Included(v) => Bound::Included(key(v)?),
+        Bound::Excluded(v) => Bound::Excluded(key(v)?),
+        Bound::Unbounded => Bound::Unbounded,
+    })
+}
+
+/// Stores a payload per ID and indexes every field for equality and range
+/// lookups, each answered as a bitmap of matching IDs.
+///
+/// `Not` is taken relative to the IDs that have a payload, so vectors
+/// without one never match a filter.
+#[derive(Clone)]
+pub struct PayloadStore<B: Bitmap> {
+    payloads: HashMap<ID, Payload>,
+    ids: B,
+    index: HashMap<String, BTreeMap<IndexKey, B>>,
+}
+
+impl<B: Bitmap> Default for PayloadStore<B> {
+    fn default() -> Self {
+        Self {
+            payloads: HashMap::new(),
+            ids: B::new(),
+            index: HashMap::new(),
+        }
+    }
+}
+
+impl<B: Bitmap> PayloadStore<B> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.payloads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.payloads.is_empty()
+    }
+
+    pub fn get(&self, id: ID) -> Option<&Payload> {
+        self.payloads.get(&id)
+    }
+
+    /// A group key for `VectorStore::find_nearest_grouped` that groups by
+    /// the value of `field`. IDs without the field are left out.
+    pub fn group_key<'a>(&'a self, field: &'a str) -> impl Fn(ID) -> Option<Value> + 'a {
+        move |id| self.payloads.get(&id)?.get(field).cloned()
+    }
+
+    /// Replaces the payload for `id`.
+    pub fn set(&mut self, id: ID, payload: Payload) -> Result<()> {
+        if id > B::MAX_ID {
+            return Err(anyhow!("ID {} is out of range for this bitmap", id));
+        }
+        self.remove(id);
+        for (field, value) in payload.iter() {
+            let keys = self.index.entry(field.clone()).or_default();
+            for key in index_keys(value) {
+                keys.entry(key).or_default().add(id);
+            }
+        }
+        self.ids.add(id);
+        self.payloads.insert(id, payload);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: ID) -> Option<Payload> {
+        let payload = self.payloads.remove(&id)?;
+        for (field, value) in payload.iter() {
+            let keys = self.index.get_mut(field).unwrap();
+            for key in index_keys(value) {
+                let bm = keys.get_mut(&key).unwrap();
+                bm.remove(id);
+                if bm.is_empty() {
+                    keys.remove(&key);
+                }
+            }
+            if keys.is_empty() {
+                self.index.remove(field);
+            }
+        }
+        self.ids.remove(id);
+        Some(payload)
+    }
+
+    /// Evaluates a filter into the bitmap of matching IDs.
+    pub fn compile(&self, filter: &Filter) -> Result<B> {
+        match filter {
+            Filter::Eq(field, value) => {
+                let mut out = B::new();
+                if let Some(keys) = self.index.get(field) {
+                    for key in index_keys(value) {
+                        if let Some(bm) = keys.get(&key) {
+                            out.or(bm);
+                        }
+                    }
+                }
+                Ok(out)
+            }
+            Filter::Range(field, lower, upper) => {
+                let mut out = B::new();
+                let Some(range) = clamp_range(bound_key(lower)?, bound_key(upper)?)? else {
+                    return Ok(out);
+                };
+                if let Some(keys) = self.index.get(field) {
+                    for bm in keys.range(range).map(|(_, bm)| bm) {
+                        out.or(bm);
+                    }
+                }
+                Ok(out)
+            }
+            Filter::And(fs) => {
+                let mut out = self.ids.clone();
+                for f in fs {
+                    out.and(&self.compile(f)?);
+                }
+                Ok(out)
+            }
+            Filter::Or(fs) => {
+                let mut out = B::new();
+                for f in fs {
+                    out.or(&self.compile(f)?);
+                }
+                Ok(out)
+            }
+            Filter::Not(f) => {
+                let mut out = self.ids.clone();
+                out.and_not(&self.compile(f)?);
+                Ok(out)
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&self.payloads)?)
+    }
+
+    /// Loads payloads written by `to_bytes`, rebuilding the indexes.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let payloads: HashMap<ID, Payload> = serde_json::from_slice(data)?;
+        let mut out = Self::new();
+        for (id, payload) in payloads {
+            out.set(id, payload)?;
+        }
+        Ok(out)
+    }
+}
+
+// Replaces an open end with the edge of the bounded end's kind. Returns
+// None for a range that can't hold anything, which `BTreeMap::range` would
+// panic on.
+fn clamp_range(
+    lower: Bound<IndexKey>,
+    upper: Bound<IndexKey>,
+) -> Result<Option<(Bound<IndexKey>, Bound<IndexKey>)>> {
+    if let (Bound::Included(l) | Bound::Excluded(l), Bound::Included(u) | Bound::Excluded(u)) =
+        (&lower, &upper)
+    {
+        if std::mem::discriminant(l) != std::mem::discriminant(u) {
+            return Err(anyhow!(
+                "Range bounds must be the same kind, not {:?} and {:?}",
+                l,
+                u
+            ));
+        }
+    }
+    let kind = match (&lower, &upper) {
+        (Bound::Included(k) | Bound::Excluded(k), _) => k.clone(),
+        (_, Bound::Included(k) | Bound::Excluded(k)) => k.clone(),
+        _ => return Err(anyhow!("Range filters need at least one bound")),
+    };
+    let lower = match (lower, &kind) {
+        (Bound::Unbounded, IndexKey::Num(_)) => Bound::Excluded(IndexKey::Bool(true)),
+        (Bound::Unbounded, IndexKey::Str(_)) => Bound::Included(IndexKey::Str(String::new())),
+        (b, _) => b,
+    };
+    let upper = match (upper, &kind) {
+        (Bound::Unbounded, IndexKey::Num(_)) => Bound::Excluded(IndexKey::Str(String::new())),
+        (b, _) => b,
+    };
+    let empty = match (&lower, &upper) {
+        (Bound::Included(l), Bound::Included(u)) => l > u,
+        (Bound::Included(l) | Bound::Excluded(l), Bound::Included(u) | Bound::Excluded(u)) => {
+            l >= u
+        }
+        _ => false,
+    };
+    Ok((!empty).then_some((lower, upper)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::RoaringTreemap;
+
+    fn store() -> PayloadStore<RoaringTreemap> {
+        let mut ps = PayloadStore::new();
+        let rows: [(&str, &str, i64, &[&str]); 4] = [
+            ("acme", "en", 100, &["red", "blue"]),
+            ("acme", "fr", 200, &["blue"]),
+            ("globex", "en", 300, &[]),
+            ("globex", "de", 400, &["red"]),
+        ];
+        for (id, (tenant, lang, ts, tags)) in rows.into_iter().enumerate() {
+            let mut p = Payload::new();
+            p.insert("tenant".into(), tenant.into());
+            p.insert("lang".into(), lang.into());
+            p.insert("ts".into(), ts.into());
+            let tags = tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+            p.insert("tags".into(), tags.into());
+            ps.set(id as ID, p).unwrap();
+        }
+        ps
+    }
+
+    fn ids(ps: &PayloadStore<RoaringTreemap>, f: &Filter) -> Vec<ID> {
+        ps.compile(f).unwrap().iter_elems().collect()
+    }
+
+    #[test]
+    fn filters() {
+        let ps = store();
+        assert_eq!(ids(&ps, &Filter::eq("tenant", "acme")), vec![0, 1]);
+        assert_eq!(ids(&ps, &Filter::eq("tags", "red")), vec![0, 3]);
+        assert_eq!(ids(&ps, &Filter::eq("missing", 1)), Vec::<ID>::new());
+        let f = Filter::range("ts", Value::from(200)..Value::from(400));
+        assert_eq!(ids(&ps, &f), vec![1, 2]);
+        assert_eq!(
+            ids(&ps, &Filter::range("ts", Value::from(250.5)..)),
+            vec![2, 3]
+        );
+        assert_eq!(ids(&ps, &Filter::range("ts", ..=Value::from(100))), vec![0]);
+        let f = Filter::eq("tenant", "globex")
+            .and(Filter::eq("lang", "en").not())
+            .or(Filter::eq("lang", "fr"));
+        assert_eq!(ids(&ps, &f), vec![1, 3]);
+        assert!(ps.compile(&Filter::range("ts", ..)).is_err());
+    }
+
+    #[test]
+    fn empty_and_mixed_ranges() {
+        let ps = store();
+        let none = Vec::<ID>::new();
+        let f = Filter::range("ts", Value::from(400)..Value::from(200));
+        assert_eq!(ids(&ps, &f), none);
+        let f = Filter::Range(
+            "ts".into(),
+            Bound::Excluded(Value::from(200)),
+            Bound::Excluded(Value::from(200)),
+        );
+        assert_eq!(ids(&ps, &f), none);
+        assert_eq!(
+            ids(
+                &ps,
+                &Filter::range("ts", Value::from(200)..Value::from(200))
+            ),
+            none
+        );
+        assert_eq!(
+            ids(
+                &ps,
+                &Filter::range("ts", Value::from(200)..=Value::from(200))
+            ),
+            vec![1]
+        );
+        let f = Filter::range("lang", Value::from("en")..Value::from(5));
+        assert!(ps.compile(&f).is_err());
+    }
+
+    #[test]
+    fn updates_and_round_trips() {
+        let mut ps = store();
+        let mut p = Payload::new();
+        p.insert("tenant".into(), "initech".into());
+        ps.set(0, p).unwrap();
+        assert_eq!(ids(&ps, &Filter::eq("tenant", "acme")), vec![1]);
+        assert_eq!(ids(&ps, &Filter::eq("tags", "red")), vec![3]);
+        ps.remove(3);
+        assert_eq!(ids(&ps, &Filter::eq("tags", "red")), Vec::<ID>::new());
+
+        let back = PayloadStore::<RoaringTreemap>::from_bytes(&ps.to_bytes().unwrap()).unwrap();
+        assert_eq!(back.len(), 3);
+        assert_eq!(back.get(0), ps.get(0));
+        assert_eq!(ids(&back, &Filter::eq("tenant", "initech")), vec![0]);
+        assert_eq!(
+            ids(&back, &Filter::range("ts", Value::from(0)..)),
+            vec![1, 2]
+        );
+    }
+}
@@ -1,45 +1,115 @@
-use crate::{vector::distance, Vector};
-use anyhow::Result;
-use half::{bf16, vec::HalfFloatVecExt};
+use crate::{spaces::half_float, vector::cosine_similarity, Vector};
+#[cfg(target_endian = "little")]
+use crate::{spaces::simple::dot_product, unaligned_f32::UnalignedF32Slice};
+use anyhow::{anyhow, Result};
+use half::{bf16, f16, slice::HalfFloatSliceExt, vec::HalfFloatVecExt};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-pub trait Quantization: Default {
+/// Lowers vectors to a compact stored form and scores queries against it.
+///
+/// A quantizer may carry state learned from the data, such as calibrated
+/// ranges or codebooks. Backends own their quantizer, and the disk backend
+/// persists it, serialized, with the store.
+pub trait Quantization: Default + Clone + Serialize + DeserializeOwned {
     type Lower: Clone;
-    fn similarity(x: &Self::Lower, y: &Self::Lower) -> Result<f32>;
-    fn compare(x: &Vector, y: &Self::Lower) -> Result<f32>;
-    fn lower(vec: Vector) -> Result<Self::Lower>;
-    fn vector_size(dimensions: usize) -> usize;
-    fn marshal(v: &Self::Lower, array: &mut [u8]) -> Result<()>;
-    fn unmarshal(array: &[u8]) -> Result<Self::Lower>;
-    fn name() -> &'static str;
+    /// Fits the quantizer to a sample of the vectors it will store.
+    /// Quantizers with nothing to learn ignore it.
+    fn train(&mut self, _sample: &[Vector]) -> Result<()> {
+        Ok(())
+    }
+    /// Tells the quantizer how many dimensions the vectors it unmarshals
+    /// have, for layouts that don't record it. Backends call it on open.
+    fn set_dimensions(&mut self, _dimensions: usize) {}
+    fn similarity(&self, x: &Self::Lower, y: &Self::Lower) -> Result<f32>;
+    fn compare(&self, x: &Vector, y: &Self::Lower) -> Result<f32>;
+    /// Scores a query against a vector as `marshal` laid it out, such as a
+    /// slot in a mapped file. The default unmarshals it first; quantizers
+    /// override it to read the bytes in place.
+    fn compare_bytes(&self, x: &Vector, array: &[u8]) -> Result<f32> {
+        self.compare(x, &self.unmarshal(array)?)
+    }
+    /// Prepares to score the query `x` against many vectors, as `compare`
+    /// would. Quantizers with state to build per query, such as lookup
+    /// tables, build it here once per search rather than per comparison.
+    fn scorer<'a>(&'a self, x: &'a Vector) -> Result<impl Fn(&Self::Lower) -> Result<f32> + 'a> {
+        Ok(move |y: &Self::Lower| self.compare(x, y))
+    }
+    /// Like `scorer`, for vectors as `marshal` laid them out.
+    fn bytes_scorer<'a>(&'a self, x: &'a Vector) -> Result<impl Fn(&[u8]) -> Result<f32> + 'a> {
+        Ok(move |array: &[u8]| self.compare_bytes(x, array))
+    }
+    fn lower(&self, vec: Vector) -> Result<Self::Lower>;
+    fn raise(&self, v: &Self::Lower) -> Result<Vector>;
+    fn vector_size(&self, dimensions: usize) -> usize;
+    fn marshal(&self, v: &Self::Lower, array: &mut [u8]) -> Result<()>;
+    fn unmarshal(&self, array: &[u8]) -> Result<Self::Lower>;
+    fn name(&self) -> &'static str;
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct NoQuantization {}
 
+#[cfg(target_endian = "little")]
+impl NoQuantization {
+    // The query as the kernels read it, and its norm, which only need
+    // working out once per query.
+    fn query_view(x: &Vector) -> (&UnalignedF32Slice, f32) {
+        let x: &UnalignedF32Slice = x.into();
+        (x, dot_product(x, x).sqrt())
+    }
+
+    fn cosine_bytes(x: &UnalignedF32Slice, x_norm: f32, array: &[u8]) -> Result<f32> {
+        let y = UnalignedF32Slice::from_bytes(array)?;
+        let norms = x_norm * dot_product(y, y).sqrt();
+        if norms == 0.0 {
+            return Ok(0.0);
+        }
+        Ok(dot_product(x, y) / norms)
+    }
+}
+
 impl Quantization for NoQuantization {
     type Lower = Vector;
 
-    fn similarity(x: &Self::Lower, y: &Self::Lower) -> Result<f32> {
-        Ok(distance(x, y))
+    fn similarity(&self, x: &Self::Lower, y: &Self::Lower) -> Result<f32> {
+        Ok(cosine_similarity(x, y))
+    }
+
+    fn compare(&self, x: &Vector, y: &Self::Lower) -> Result<f32> {
+        Ok(cosine_similarity(x, y))
     }
 
-    fn compare(x: &Vector, y: &Self::Lower) -> Result<f32> {
-        Ok(distance(x, y))
+    // Stored floats are little-endian, so elsewhere they need swapping.
+    #[cfg(target_endian = "little")]
+    fn compare_bytes(&self, x: &Vector, array: &[u8]) -> Result<f32> {
+        let (x, x_norm) = Self::query_view(x);
+        Self::cosine_bytes(x, x_norm, array)
     }
 
-    fn lower(vec: Vector) -> Result<Self::Lower> {
+    #[cfg(target_endian = "little")]
+    fn bytes_scorer<'a>(&'a self, x: &'a Vector) -> Result<impl Fn(&[u8]) -> Result<f32> + 'a> {
+        let (x, x_norm) = Self::query_view(x);
+        Ok(move |array: &[u8]| Self::cosine_bytes(x, x_norm, array))
+    }
+
+    fn lower(&self, vec: Vector) -> Result<Self::Lower> {
         Ok(vec)
     }
 
-    fn name() -> &'static str {
+    fn raise(&self, v: &Self::Lower) -> Result<Vector> {
+        Ok(v.clone())
+    }
+
+    fn name(&self) -> &'static str {
         "none"
     }
 
-    fn vector_size(dimensions: usize) -> usize {
+    fn vector_size(&self, dimensions: usize) -> usize {
         4 * dimensions
     }
 
-    fn marshal(v: &Self::Lower, array: &mut [u8]) -> Result<()> {
+    fn marshal(&self, v: &Self::Lower, array: &mut [u8]) -> Result<()> {
         for (i, f) in v.iter().enumerate() {
             let bytes = f.to_le_bytes();
             let _ = &array[i * 4..i * 4 + 4].copy_from_slice(&bytes);
@@ -47,7 +117,7 @@ impl Quantization for NoQuantization {
         Ok(())
     }
 
-    fn unmarshal(array: &[u8]) -> Result<Self::Lower> {
+    fn unmarshal(&self, array: &[u8]) -> Result<Self::Lower> {
         let mut vec = Vec::new();
         for i in (0..array.len()).step_by(4) {
             let bytes = &array[i..i + 4];
@@ -58,36 +128,46 @@ impl Quantization for NoQuantization {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct BF16Quantization {}
 
 impl Quantization for BF16Quantization {
     type Lower = Vec<half::bf16>;
 
-    fn similarity(x: &Self::Lower, y: &Self::Lower) -> Result<f32> {
-        let fx = x.iter().map(|v| v.to_f32()).collect();
-        let fy = y.iter().map(|v| v.to_f32()).collect();
-        Ok(distance(&fx, &fy))
+    fn similarity(&self, x: &Self::Lower, y: &Self::Lower) -> Result<f32> {
+        Ok(half_float::cosine_bf16(x, y))
     }
 
-    fn compare(x: &Vector, y: &Self::Lower) -> Result<f32> {
-        let fy = y.iter().map(|v| v.to_f32()).collect();
-        Ok(distance(x, &fy))
+    fn compare(&self, x: &Vector, y: &Self::Lower) -> Result<f32> {
+        Ok(half_float::cosine_f32_bf16(x, y))
     }
 
-    fn lower(vec: Vector) -> Result<Self::Lower> {
+    #[cfg(target_endian = "little")]
+    fn compare_bytes(&self, x: &Vector, array: &[u8]) -> Result<f32> {
+        use half::slice::HalfBitsSliceExt;
+        match half_bits(array) {
+            Some(y) => Ok(half_float::cosine_f32_bf16(x, y.reinterpret_cast())),
+            None => self.compare(x, &self.unmarshal(array)?),
+        }
+    }
+
+    fn lower(&self, vec: Vector) -> Result<Self::Lower> {
         Ok(Vec::from_f32_slice(vec.as_slice()))
     }
 
-    fn name() -> &'static str {
+    fn raise(&self, v: &Self::Lower) -> Result<Vector> {
+        Ok(v.to_f32_vec())
+    }
+
+    fn name(&self) -> &'static str {
         "bf16"
     }
 
-    fn vector_size(dimensions: usize) -> usize {
+    fn vector_size(&self, dimensions: usize) -> usize {
         2 * dimensions
     }
 
-    fn marshal(v: &Self::Lower, array: &mut [u8]) -> Result<()> {
+    fn marshal(&self, v: &Self::Lower, array: &mut [u8]) -> Result<()> {
         for (i, f) in v.iter().enumerate() {
             let bytes = f.to_le_bytes();
             let _ = &array[i * 2..i * 2 + 2].copy_from_slice(&bytes);
@@ -95,7 +175,7 @@ impl Quantization for BF16Quantization {
         Ok(())
     }
 
-    fn unmarshal(array: &[u8]) -> Result<Self::Lower> {
+    fn unmarshal(&self, array: &[u8]) -> Result<Self::Lower> {
         let mut vec = Vec::new();
         for i in (0..array.len()).step_by(2) {
             let bytes = &array[i..i + 2];
@@ -105,3 +185,854 @@ impl Quantization for BF16Quantization {
         Ok(vec)
     }
 }
+
+/// IEEE half precision, laid out and named to match the Go
+/// `Float16Quantization`, so either side can read the other's vectors.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct F16Quantization {}
+
+impl Quantization for F16Quantization {
+    type Lower = Vec<f16>;
+
+    fn similarity(&self, x: &Self::Lower, y: &Self::Lower) -> Result<f32> {
+        Ok(half_float::cosine_f16(x, y))
+    }
+
+    fn compare(&self, x: &Vector, y: &Self::Lower) -> Result<f32> {
+        Ok(half_float::cosine_f32_f16(x, y))
+    }
+
+    #[cfg(target_endian = "little")]
+    fn compare_bytes(&self, x: &Vector, array: &[u8]) -> Result<f32> {
+        use half::slice::HalfBitsSliceExt;
+        match half_bits(array) {
+            Some(y) => Ok(half_float::cosine_f32_f16(x, y.reinterpret_cast())),
+            None => self.compare(x, &self.unmarshal(array)?),
+        }
+    }
+
+    fn lower(&self, vec: Vector) -> Result<Self::Lower> {
+        Ok(Vec::from_f32_slice(vec.as_slice()))
+    }
+
+    fn raise(&self, v: &Self::Lower) -> Result<Vector> {
+        Ok(v.to_f32_vec())
+    }
+
+    fn name(&self) -> &'static str {
+        "float16"
+    }
+
+    fn vector_size(&self, dimensions: usize) -> usize {
+        2 * dimensions
+    }
+
+    fn marshal(&self, v: &Self::Lower, array: &mut [u8]) -> Result<()> {
+        for (i, f) in v.iter().enumerate() {
+            array[i * 2..i * 2 + 2].copy_from_slice(&f.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    fn unmarshal(&self, array: &[u8]) -> Result<Self::Lower> {
+        Ok(array
+            .chunks_exact(2)
+            .map(|b| f16::from_le_bytes([b[0], b[1]]))
+            .collect())
+    }
+}
+
+// Reads stored halves in place, if the bytes are aligned for it. Slots in a
+// vector file always are, as each is a whole number of halves long.
+#[cfg(target_endian = "little")]
+fn half_bits(array: &[u8]) -> Option<&[u16]> {
+    bytemuck::try_cast_slice(array).ok()
+}
+
+/// How `Int8Quantization` picks the range each code spans when trained.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Calibration {
+    /// One range, from the smallest to the largest value in the sample.
+    Global,
+    /// A range per dimension, from its smallest to its largest value.
+    PerDimension,
+    /// A range per dimension, clipped to the given percentile at either
+    /// end (say, 1.0 for the 1st to the 99th), so outliers don't stretch it.
+    Percentile(f32),
+}
+
+/// Scalar quantization to one byte per dimension. Value `i` is stored as a
+/// code `c` standing for `lo[i] + step[i] * c`, over ranges learned by
+/// `train`; values outside the range are clipped to it. Each dimension has
+/// its own range, though with `Calibration::Global` they're all the same.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Int8Quantization {
+    calibration: Calibration,
+    lo: Vec<f32>,
+    step: Vec<f32>,
+}
+
+impl Default for Int8Quantization {
+    fn default() -> Self {
+        Self::new(Calibration::PerDimension)
+    }
+}
+
+impl Int8Quantization {
+    /// An untrained quantizer, whose codes span [-1, 1], which holds any
+    /// normalized vector.
+    pub fn new(calibration: Calibration) -> Self {
+        let mut out = Self {
+            calibration,
+            lo: Vec::new(),
+            step: Vec::new(),
+        };
+        out.set_ranges(vec![(-1.0, 1.0)]);
+        out
+    }
+
+    fn set_ranges(&mut self, ranges: Vec<(f32, f32)>) {
+        (self.lo, self.step) = ranges
+            .into_iter()
+            // A range of one value still needs a nonzero step.
+            .map(|(lo, hi)| (lo, ((hi - lo) / 255.0).max(f32::EPSILON)))
+            .unzip();
+    }
+
+    fn range(&self, i: usize) -> (f32, f32) {
+        let i = if self.lo.len() == 1 { 0 } else { i };
+        (self.lo[i], self.step[i])
+    }
+
+    fn check(&self, dims: usize) -> Result<()> {
+        if self.lo.len() != 1 && self.lo.len() != dims {
+            return Err(anyhow!(
+                "Int8 ranges cover {} dimensions, not {}",
+                self.lo.len(),
+                dims
+            ));
+        }
+        Ok(())
+    }
+
+    // Cosine similarity, decoding one code at a time rather than the whole
+    // vector.
+    fn cosine(&self, x: impl Iterator<Item = (usize, f32)>, y: &[u8]) -> f32 {
+        let (mut dot, mut xx, mut yy) = (0.0, 0.0, 0.0);
+        for ((i, a), c) in x.zip(y.iter()) {
+            let (lo, step) = self.range(i);
+            let b = lo + step * *c as f32;
+            dot += a * b;
+            xx += a * a;
+            yy += b * b;
+        }
+        let norms = (xx * yy).sqrt();
+        if norms == 0.0 {
+            return 0.0;
+        }
+        dot / norms
+    }
+
+    fn decode<'a>(&'a self, y: &'a [u8]) -> impl Iterator<Item = (usize, f32)> + 'a {
+        y.iter().enumerate().map(|(i, c)| {
+            let (lo, step) = self.range(i);
+            (i, lo + step * *c as f32)
+        })
+    }
+}
+
+fn min_max(values: &[f32]) -> (f32, f32) {
+    values
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(*v), hi.max(*v)))
+}
+
+impl Quantization for Int8Quantization {
+    type Lower = Vec<u8>;
+
+    /// Learns the ranges, as set by the calibration.
+    fn train(&mut self, sample: &[Vector]) -> Result<()> {
+        let sample = normalized_sample(sample)?;
+        let dims = sample[0].len();
+        let column = |i: usize| -> Vec<f32> { sample.iter().map(|v| v[i]).collect() };
+        let ranges = match self.calibration {
+            Calibration::Global => {
+                let all: Vec<f32> = sample.iter().flatten().copied().collect();
+                vec![min_max(&all)]
+            }
+            Calibration::PerDimension => (0..dims).map(|i| min_max(&column(i))).collect(),
+            Calibration::Percentile(p) => {
+                if !(0.0..50.0).contains(&p) {
+                    return Err(anyhow!("Percentile {} is out of range", p));
+                }
+                (0..dims)
+                    .map(|i| {
+                        let mut col = column(i);
+                        col.sort_by(f32::total_cmp);
+                        let at =
+                            |q: f32| col[(q / 100.0 * (col.len() - 1) as f32).round() as usize];
+                        (at(p), at(100.0 - p))
+                    })
+                    .collect()
+            }
+        };
+        self.set_ranges(ranges);
+        Ok(())
+    }
+
+    fn similarity(&self, x: &Self::Lower, y: &Self::Lower) -> Result<f32> {
+        Ok(self.cosine(self.decode(x), y))
+    }
+
+    fn compare(&self, x: &Vector, y: &Self::Lower) -> Result<f32> {
+        Ok(self.cosine(x.iter().copied().enumerate(), y))
+    }
+
+    fn compare_bytes(&self, x: &Vector, array: &[u8]) -> Result<f32> {
+        Ok(self.cosine(x.iter().copied().enumerate(), array))
+    }
+
+    fn lower(&self, vec: Vector) -> Result<Self::Lower> {
+        self.check(vec.len())?;
+        Ok(vec
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let (lo, step) = self.range(i);
+                ((v - lo) / step).round().clamp(0.0, 255.0) as u8
+            })
+            .collect())
+    }
+
+    fn raise(&self, v: &Self::Lower) -> Result<Vector> {
+        self.check(v.len())?;
+        Ok(self.decode(v).map(|(_, f)| f).collect())
+    }
+
+    fn name(&self) -> &'static str {
+        "int8"
+    }
+
+    fn vector_size(&self, dimensions: usize) -> usize {
+        dimensions
+    }
+
+    fn marshal(&self, v: &Self::Lower, array: &mut [u8]) -> Result<()> {
+        array[..v.len()].copy_from_slice(v);
+        Ok(())
+    }
+
+    fn unmarshal(&self, array: &[u8]) -> Result<Self::Lower> {
+        Ok(array.to_vec())
+    }
+}
+
+/// One sign bit per dimension, packed 64 to a word.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignBits {
+    dims: usize,
+    words: Vec<u64>,
+}
+
+impl SignBits {
+    fn from_signs(v: &[f32]) -> Self {
+        Self {
+            dims: v.len(),
+            words: v.chunks(64).map(sign_word).collect(),
+        }
+    }
+}
+
+fn sign_word(chunk: &[f32]) -> u64 {
+    chunk
+        .iter()
+        .enumerate()
+        .fold(0, |w, (i, f)| if *f >= 0.0 { w | 1 << i } else { w })
+}
+
+/// Sign-bit quantization: each dimension keeps only whether it's negative,
+/// and similarity is `1 - 2 * hamming / dimensions`, counted with popcount.
+///
+/// It's a very cheap, very coarse first pass. To get precision back, use it
+/// as the coarse half of a `TwoStageBackend` and search `with_rerank`.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct BinaryQuantization {
+    // The bytes don't say how many bits of the last one are padding, so
+    // the backend sets this; it's not part of the quantizer's state.
+    #[serde(skip)]
+    dims: usize,
+}
+
+impl BinaryQuantization {
+    fn score(dims: usize, hamming: u32) -> f32 {
+        if dims == 0 {
+            return 0.0;
+        }
+        1.0 - 2.0 * hamming as f32 / dims as f32
+    }
+}
+
+impl Quantization for BinaryQuantization {
+    type Lower = SignBits;
+
+    fn similarity(&self, x: &Self::Lower, y: &Self::Lower) -> Result<f32> {
+        let hamming = x
+            .words
+            .iter()
+            .zip(y.words.iter())
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum();
+        Ok(Self::score(x.dims, hamming))
+    }
+
+    // Packs the query's signs a word at a time as it goes.
+    fn compare(&self, x: &Vector, y: &Self::Lower) -> Result<f32> {
+        let hamming = x
+            .chunks(64)
+            .zip(y.words.iter())
+            .map(|(a, b)| (sign_word(a) ^ b).count_ones())
+            .sum();
+        Ok(Self::score(x.len(), hamming))
+    }
+
+    fn compare_bytes(&self, x: &Vector, array: &[u8]) -> Result<f32> {
+        let hamming = x
+            .chunks(64)
+            .zip(array.chunks(8))
+            .map(|(a, c)| {
+                let word = c.iter().rev().fold(0, |w, b| w << 8 | *b as u64);
+                (sign_word(a) ^ word).count_ones()
+            })
+            .sum();
+        Ok(Self::score(x.len(), hamming))
+    }
+
+    fn lower(&self, vec: Vector) -> Result<Self::Lower> {
+        Ok(SignBits::from_signs(&vec))
+    }
+
+    fn raise(&self, v: &Self::Lower) -> Result<Vector> {
+        let scale = 1.0 / (v.dims as f32).sqrt();
+        Ok((0..v.dims)
+            .map(|i| {
+                if v.words[i / 64] & 1 << (i % 64) != 0 {
+                    scale
+                } else {
+                    -scale
+                }
+            })
+            .collect())
+    }
+
+    fn name(&self) -> &'static str {
+        "binary"
+    }
+
+    fn set_dimensions(&mut self, dimensions: usize) {
+        self.dims = dimensions;
+    }
+
+    fn vector_size(&self, dimensions: usize) -> usize {
+        dimensions.div_ceil(8)
+    }
+
+    fn marshal(&self, v: &Self::Lower, array: &mut [u8]) -> Result<()> {
+        for (i, b) in array[..v.dims.div_ceil(8)].iter_mut().enumerate() {
+            *b = (v.words[i / 8] >> (8 * (i % 8))) as u8;
+        }
+        Ok(())
+    }
+
+    fn unmarshal(&self, array: &[u8]) -> Result<Self::Lower> {
+        if self.dims == 0 || array.len() != self.dims.div_ceil(8) {
+            return Err(anyhow!(
+                "Expected {} dimensions of sign bits, got {} bytes",
+                self.dims,
+                array.len()
+            ));
+        }
+        let words = array
+            .chunks(8)
+            .map(|c| c.iter().rev().fold(0, |w, b| w << 8 | *b as u64))
+            .collect();
+        Ok(SignBits {
+            dims: self.dims,
+            words,
+        })
+    }
+}
+
+// Training samples are normalized first, as the backends normalize what
+// they store.
+fn normalized_sample(sample: &[Vector]) -> Result<Vec<Vector>> {
+    let Some(dims) = sample.first().map(|v| v.len()) else {
+        return Err(anyhow!("Can't train on an empty sample"));
+    };
+    if sample.iter().any(|v| v.len() != dims) {
+        return Err(anyhow!("dimensions don't match"));
+    }
+    Ok(sample
+        .iter()
+        .map(|v| {
+            let mut v = v.clone();
+            crate::vector::normalize(&mut v);
+            v
+        })
+        .collect())
+}
+
+// One for every value of a byte code.
+const PQ_CENTROIDS: usize = 256;
+
+const DEFAULT_PQ_ITERATIONS: usize = 10;
+
+fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+fn nearest_centroid(centroids: &[Vector], point: &[f32]) -> usize {
+    centroids
+        .iter()
+        .map(|c| squared_distance(c, point))
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+// Lloyd's algorithm, seeded with a random sample of the points. A centroid
+// that loses all its points stays where it was.
+fn kmeans(points: &[&[f32]], k: usize, iterations: usize, rng: &mut impl Rng) -> Vec<Vector> {
+    let mut centroids: Vec<Vector> = rand::seq::index::sample(rng, points.len(), k)
+        .into_iter()
+        .map(|i| points[i].to_vec())
+        .collect();
+    let width = centroids[0].len();
+    for _ in 0..iterations {
+        let mut sums = vec![vec![0.0; width]; k];
+        let mut counts = vec![0usize; k];
+        for p in points {
+            let c = nearest_centroid(&centroids, p);
+            sums[c].iter_mut().zip(p.iter()).for_each(|(s, x)| *s += x);
+            counts[c] += 1;
+        }
+        for ((centroid, sum), n) in centroids.iter_mut().zip(sums).zip(counts) {
+            if n > 0 {
+                *centroid = sum.into_iter().map(|s| s / n as f32).collect();
+            }
+        }
+    }
+    centroids
+}
+
+// Checks a code against a codebook of `len`, which may be smaller than
+// 256 if the sample was.
+fn code_index(code: u8, len: usize) -> Result<usize> {
+    if code as usize >= len {
+        return Err(anyhow!("Invalid product quantization code {}", code));
+    }
+    Ok(code as usize)
+}
+
+// The asymmetric distance tables for one query: its dot product with every
+// centroid, and every centroid's squared norm.
+struct QueryTables {
+    query_norm: f32,
+    dots: Vec<Vec<f32>>,
+    norms: Vec<Vec<f32>>,
+}
+
+impl QueryTables {
+    fn score(&self, codes: &[u8]) -> Result<f32> {
+        if codes.len() != self.dots.len() {
+            return Err(anyhow!(
+                "Expected {} product quantization codes, not {}",
+                self.dots.len(),
+                codes.len()
+            ));
+        }
+        let (mut dot, mut norm) = (0.0, 0.0);
+        for (m, code) in codes.iter().enumerate() {
+            let i = code_index(*code, self.dots[m].len())?;
+            dot += self.dots[m][i];
+            norm += self.norms[m][i];
+        }
+        let norms = self.query_norm * norm.sqrt();
+        if norms == 0.0 {
+            return Ok(0.0);
+        }
+        Ok(dot / norms)
+    }
+}
+
+/// Product quantization: vectors split into `M` equal subspaces, each
+/// stored as a one-byte code for the nearest centroid in that subspace's
+/// codebook. `train` learns the codebooks with k-means, up to 256
+/// centroids apiece; there's nothing to quantize with until then. K-means
+/// starts from a sample drawn with a fixed seed, so training on the same
+/// vectors gives the same codebooks.
+///
+/// A query is scored against the codes with lookup tables of its distance
+/// to each centroid, built once per search by `scorer`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProductQuantization<const M: usize> {
+    iterations: usize,
+    #[serde(default)]
+    seed: u64,
+    codebooks: Vec<Vec<Vector>>,
+}
+
+impl<const M: usize> Default for ProductQuantization<M> {
+    fn default() -> Self {
+        Self::new(DEFAULT_PQ_ITERATIONS)
+    }
+}
+
+impl<const M: usize> ProductQuantization<M> {
+    /// An untrained quantizer that runs `iterations` rounds of k-means when
+    /// it's trained.
+    pub fn new(iterations: usize) -> Self {
+        Self {
+            iterations,
+            seed: 0,
+            codebooks: Vec::new(),
+        }
+    }
+
+    /// Seeds k-means with `seed` instead of 0.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    fn check(&self, dims: usize) -> Result<usize> {
+        if self.codebooks.len() != M {
+            return Err(anyhow!("Product quantization needs trained codebooks"));
+        }
+        if !dims.is_multiple_of(M) {
+            return Err(anyhow!("dimensions don't match"));
+        }
+        Ok(dims / M)
+    }
+
+    fn build_tables(&self, x: &Vector) -> Result<QueryTables> {
+        let width = self.check(x.len())?;
+        let table = |f: &dyn Fn(usize, &Vector) -> f32| -> Vec<Vec<f32>> {
+            self.codebooks
+                .iter()
+                .enumerate()
+                .map(|(m, book)| book.iter().map(|c| f(m, c)).collect())
+                .collect()
+        };
+        let sub = |m: usize| &x[m * width..][..width];
+        Ok(QueryTables {
+            query_norm: x.iter().map(|f| f * f).sum::<f32>().sqrt(),
+            dots: table(&|m, c| c.iter().zip(sub(m)).map(|(a, b)| a * b).sum()),
+            norms: table(&|_, c| c.iter().map(|f| f * f).sum()),
+        })
+    }
+}
+
+impl<const M: usize> Quantization for ProductQuantization<M> {
+    type Lower = Vec<u8>;
+
+    fn train(&mut self, sample: &[Vector]) -> Result<()> {
+        let sample = normalized_sample(sample)?;
+        let dims = sample[0].len();
+        if M == 0 || !dims.is_multiple_of(M) {
+            return Err(anyhow!(
+                "{} dimensions don't split into {} subspaces",
+                dims,
+                M
+            ));
+        }
+        let width = dims / M;
+        let k = sample.len().min(PQ_CENTROIDS);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        self.codebooks = (0..M)
+            .map(|m| {
+                let points: Vec<&[f32]> = sample.iter().map(|v| &v[m * width..][..width]).collect();
+                kmeans(&points, k, self.iterations, &mut rng)
+            })
+            .collect();
+        Ok(())
+    }
+
+    fn similarity(&self, x: &Self::Lower, y: &Self::Lower) -> Result<f32> {
+        Ok(cosine_similarity(&self.raise(x)?, &self.raise(y)?))
+    }
+
+    fn compare(&self, x: &Vector, y: &Self::Lower) -> Result<f32> {
+        self.compare_bytes(x, y)
+    }
+
+    fn compare_bytes(&self, x: &Vector, array: &[u8]) -> Result<f32> {
+        self.build_tables(x)?.score(array)
+    }
+
+    fn scorer<'a>(&'a self, x: &'a Vector) -> Result<impl Fn(&Self::Lower) -> Result<f32> + 'a> {
+        let tables = self.build_tables(x)?;
+        Ok(move |y: &Self::Lower| tables.score(y))
+    }
+
+    fn bytes_scorer<'a>(&'a self, x: &'a Vector) -> Result<impl Fn(&[u8]) -> Result<f32> + 'a> {
+        let tables = self.build_tables(x)?;
+        Ok(move |array: &[u8]| tables.score(array))
+    }
+
+    fn lower(&self, vec: Vector) -> Result<Self::Lower> {
+        let width = self.check(vec.len())?;
+        Ok(self
+            .codebooks
+            .iter()
+            .zip(vec.chunks_exact(width))
+            .map(|(book, sub)| nearest_centroid(book, sub) as u8)
+            .collect())
+    }
+
+    fn raise(&self, v: &Self::Lower) -> Result<Vector> {
+        self.check(v.len())?;
+        if v.len() != M {
+            return Err(anyhow!(
+                "Expected {} product quantization codes, not {}",
+                M,
+                v.len()
+            ));
+        }
+        let mut out = Vector::new();
+        for (book, code) in self.codebooks.iter().zip(v) {
+            out.extend_from_slice(&book[code_index(*code, book.len())?]);
+        }
+        Ok(out)
+    }
+
+    fn name(&self) -> &'static str {
+        "pq"
+    }
+
+    fn vector_size(&self, _dimensions: usize) -> usize {
+        M
+    }
+
+    fn marshal(&self, v: &Self::Lower, array: &mut [u8]) -> Result<()> {
+        array[..v.len()].copy_from_slice(v);
+        Ok(())
+    }
+
+    fn unmarshal(&self, array: &[u8]) -> Result<Self::Lower> {
+        Ok(array.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(serde::Deserialize)]
+    struct Golden {
+        name: String,
+        vectors: Vec<GoldenVector>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct GoldenVector {
+        input: Vector,
+        bytes: String,
+        decoded: Vector,
+    }
+
+    // Shared with the Go tests, so both sides agree on the bytes.
+    fn golden(name: &str) -> Golden {
+        let path = format!("{}/../testdata/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn from_hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn f16_matches_go() -> Result<()> {
+        let golden = golden("float16");
+        let q = F16Quantization::default();
+        assert_eq!(golden.name, q.name());
+        for v in golden.vectors {
+            let lower = q.lower(v.input.clone())?;
+            let mut bytes = vec![0; q.vector_size(v.input.len())];
+            q.marshal(&lower, &mut bytes)?;
+            assert_eq!(bytes, from_hex(&v.bytes));
+
+            let raised = q.raise(&q.unmarshal(&bytes)?)?;
+            assert_eq!(raised.len(), v.decoded.len());
+            for (x, y) in raised.iter().zip(v.decoded.iter()) {
+                assert_eq!(x.to_bits(), y.to_bits());
+            }
+        }
+        Ok(())
+    }
+
+    fn trained<Q: Quantization>(mut q: Q, sample: &[Vector]) -> Result<Q> {
+        q.train(sample)?;
+        Ok(q)
+    }
+
+    #[test]
+    fn int8_calibrates() -> Result<()> {
+        let sample = crate::create_vector_set(16, 500);
+        for calibration in [
+            Calibration::Global,
+            Calibration::PerDimension,
+            Calibration::Percentile(1.0),
+        ] {
+            let q = trained(Int8Quantization::new(calibration), &sample)?;
+            for v in sample.iter().take(50) {
+                let mut v = v.clone();
+                crate::vector::normalize(&mut v);
+                let codes = q.lower(v.clone())?;
+                assert_eq!(codes.len(), q.vector_size(16));
+                assert!(q.compare(&v, &codes)? > 0.98);
+                assert!(cosine_similarity(&v, &q.raise(&codes)?) > 0.98);
+                assert!(q.similarity(&codes, &codes)? > 0.999);
+            }
+        }
+        // Clipping narrows the ranges.
+        let full = trained(Int8Quantization::new(Calibration::PerDimension), &sample)?;
+        let clipped = trained(Int8Quantization::new(Calibration::Percentile(5.0)), &sample)?;
+        assert!(clipped.step[0] < full.step[0]);
+        assert!(clipped.lower(vec![0.0; 3]).is_err());
+        assert!(clipped.lower(vec![-1.0; 16])?.iter().all(|c| *c == 0));
+        assert!(clipped.lower(vec![1.0; 16])?.iter().all(|c| *c == 255));
+        assert!(Int8Quantization::default().train(&[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn binary_round_trips() -> Result<()> {
+        let mut q = BinaryQuantization::default();
+        assert!(q.unmarshal(&[0xff]).is_err());
+        for dims in [5, 64, 100] {
+            q.set_dimensions(dims);
+            let x = crate::create_random_vector(dims);
+            let y = crate::create_random_vector(dims);
+            let lx = q.lower(x.clone())?;
+            let mut bytes = vec![0; q.vector_size(dims)];
+            q.marshal(&lx, &mut bytes)?;
+            assert!(bytes.iter().any(|b| *b != 0));
+            assert_eq!(bytes.len(), dims.div_ceil(8));
+            assert_eq!(q.unmarshal(&bytes)?, lx);
+            assert!(q.unmarshal(&bytes[1..]).is_err());
+
+            assert_eq!(q.similarity(&lx, &lx)?, 1.0);
+            let ly = q.lower(y.clone())?;
+            let agree = x
+                .iter()
+                .zip(y.iter())
+                .filter(|(a, b)| (**a >= 0.0) == (**b >= 0.0));
+            let expected = 2.0 * agree.count() as f32 / dims as f32 - 1.0;
+            assert!((q.similarity(&lx, &ly)? - expected).abs() < 1e-6);
+            assert!((q.compare(&x, &ly)? - expected).abs() < 1e-6);
+
+            let raised = q.raise(&lx)?;
+            assert_eq!(raised.len(), dims);
+            assert!(raised
+                .iter()
+                .zip(x.iter())
+                .all(|(r, f)| (*r >= 0.0) == (*f >= 0.0)));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn pq_trains_and_scores() -> Result<()> {
+        let sample = crate::create_vector_set(32, 1000);
+        assert!(ProductQuantization::<5>::default().train(&sample).is_err());
+        let q = trained(ProductQuantization::<8>::new(10), &sample)?;
+        assert!(ProductQuantization::<8>::default()
+            .lower(sample[0].clone())
+            .is_err());
+
+        let target = crate::create_random_vector(32);
+        for v in sample.iter().take(100) {
+            let codes = q.lower(v.clone())?;
+            assert_eq!(codes.len(), q.vector_size(32));
+            assert!(codes.iter().all(|c| (*c as usize) < q.codebooks[0].len()));
+            // Table lookups agree with scoring the decoded vector.
+            let decoded = cosine_similarity(&target, &q.raise(&codes)?);
+            assert!((q.compare(&target, &codes)? - decoded).abs() < 1e-4);
+            assert!(cosine_similarity(v, &q.raise(&codes)?) > 0.5);
+        }
+
+        // Codebooks survive a round trip.
+        let back: ProductQuantization<8> = serde_json::from_str(&serde_json::to_string(&q)?)?;
+        assert_eq!(back.codebooks, q.codebooks);
+
+        // Training is reproducible, seed by seed.
+        let again = trained(ProductQuantization::<8>::new(10), &sample)?;
+        assert_eq!(again.codebooks, q.codebooks);
+        let other = trained(ProductQuantization::<8>::new(10).with_seed(7), &sample)?;
+        assert_ne!(other.codebooks, q.codebooks);
+        Ok(())
+    }
+
+    #[test]
+    fn pq_rejects_bad_codes() -> Result<()> {
+        let sample = crate::create_vector_set(8, 100);
+        let q = trained(ProductQuantization::<2>::new(5), &sample)?;
+        let target = crate::create_random_vector(8);
+        let good = q.lower(sample[0].clone())?;
+        assert!(q.compare(&target, &good).is_ok());
+        // Only 100 centroids were trained.
+        for bad in [
+            vec![100, good[1]],
+            vec![good[0], 255],
+            vec![good[0]],
+            vec![1, 1, 1],
+        ] {
+            assert!(q.compare(&target, &bad).is_err());
+            assert!(q.compare_bytes(&target, &bad).is_err());
+            assert!(q.raise(&bad).is_err());
+        }
+        Ok(())
+    }
+
+    // Scoring in place agrees with unmarshaling first, whether or not the
+    // bytes happen to be aligned, and prepared scorers agree with both.
+    fn check_compare_bytes<Q: Quantization>(q: &Q, sample: &[Vector]) -> Result<()> {
+        let dims = sample[0].len();
+        let target = crate::create_random_vector(dims);
+        let (score, score_bytes) = (q.scorer(&target)?, q.bytes_scorer(&target)?);
+        let mut buf = vec![0; q.vector_size(dims) + 1];
+        for v in sample.iter().take(50) {
+            let mut v = v.clone();
+            crate::vector::normalize(&mut v);
+            let l = q.lower(v)?;
+            let expected = q.compare(&target, &l)?;
+            assert_eq!(score(&l)?, expected);
+            for start in [0, 1] {
+                let array = &mut buf[start..start + q.vector_size(dims)];
+                q.marshal(&l, array)?;
+                assert!((q.compare_bytes(&target, array)? - expected).abs() < 1e-5);
+                assert!((score_bytes(array)? - expected).abs() < 1e-5);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn compare_bytes_matches_compare() -> Result<()> {
+        let sample = crate::create_vector_set(37, 200);
+        check_compare_bytes(&NoQuantization::default(), &sample)?;
+        check_compare_bytes(&BF16Quantization::default(), &sample)?;
+        check_compare_bytes(&F16Quantization::default(), &sample)?;
+        check_compare_bytes(&trained(Int8Quantization::default(), &sample)?, &sample)?;
+        check_compare_bytes(&BinaryQuantization::default(), &sample)?;
+        let sample = crate::create_vector_set(32, 500);
+        check_compare_bytes(
+            &trained(ProductQuantization::<8>::new(5), &sample)?,
+            &sample,
+        )
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::Result;
+
+use crate::{Vector, VectorBackend, ID};
+
+/// A query made of weighted positive and negative examples: "like these,
+/// not like those".
+///
+/// A candidate scores the weighted similarity to the positives minus the
+/// weighted similarity to the negatives, divided by the total positive
+/// weight so that a single positive example scores like a plain search.
+/// Every example, negative ones included, picks faces in each basis.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    positive: Vec<(Vector, f32)>,
+    negative: Vec<(Vector, f32)>,
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn positive(mut self, vector: Vector, weight: f32) -> Self {
+        self.positive.push((vector, weight));
+        self
+    }
+
+    pub fn negative(mut self, vector: Vector, weight: f32) -> Self {
+        self.negative.push((vector, weight));
+        self
+    }
+
+    /// True if there are no positive examples to search from.
+    pub fn is_empty(&self) -> bool {
+        self.positive.is_empty()
+    }
+
+    pub(crate) fn vectors(&self) -> Vec<&Vector> {
+        self.positive
+            .iter()
+            .chain(self.negative.iter())
+            .map(|(v, _)| v)
+            .collect()
+    }
+
+    pub fn score(&self, backend: &impl VectorBackend, id: ID) -> Result<f32> {
+        self.score_with(|_, v| backend.compute_similarity(v, id))
+    }
+
+    pub fn score_exact(&self, backend: &impl VectorBackend, id: ID) -> Result<f32> {
+        self.score_with(|_, v| backend.compute_exact_similarity(v, id))
+    }
+
+    // Like `score`, with every example's scorer prepared once up front.
+    pub(crate) fn scorer<'a>(
+        &'a self,
+        backend: &'a impl VectorBackend,
+    ) -> Result<impl Fn(ID) -> Result<f32> + 'a> {
+        let scorers = self
+            .vectors()
+            .into_iter()
+            .map(|v| backend.scorer(v))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(move |id| self.score_with(|i, _| scorers[i](id)))
+    }
+
+    pub(crate) fn exact_scorer<'a>(
+        &'a self,
+        backend: &'a impl VectorBackend,
+    ) -> Result<impl Fn(ID) -> Result<f32> + 'a> {
+        let scorers = self
+            .vectors()
+            .into_iter()
+            .map(|v| backend.exact_scorer(v))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(move |id| self.score_with(|i, _| scorers[i](id)))
+    }
+
+    // `sim` gets each example's index in `vectors` along with it.
+    fn score_with(&self, sim: impl Fn(usize, &Vector) -> Result<f32>) -> Result<f32> {
+        let mut total = 0.0;
+        let mut weight = 0.0;
+        for (i, (v, w)) in self.positive.iter().enumerate() {
+            total += w * sim(i, v)?;
+            weight += w;
+        }
+        let first = self.positive.len();
+        for (i, (v, w)) in self.negative.iter().enumerate() {
+            total -= w * sim(first + i, v)?;
+        }
+        if weight == 0.0 {
+            return Ok(total);
+        }
+        Ok(total / weight)
+    }
+}
+
+impl From<Vector> for Query {
+    fn from(value: Vector) -> Self {
+        Query::new().positive(value, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{IndexIDIterator, MemoryBackend};
+
+    fn backend() -> MemoryBackend {
+        let mut mem = MemoryBackend::new(3, 1).unwrap();
+        let vecs = vec![
+            vec![1.0, 0.0, 0.0],
+            vec![0.0, 1.0, 0.0],
+            vec![0.0, 0.0, 1.0],
+            vec![1.0, 1.0, 0.0],
+        ];
+        for (id, v) in vecs.enumerate_ids() {
+            mem.put_vector(id, v).unwrap();
+        }
+        mem
+    }
+
+    fn ranked(q: &Query) -> Vec<ID> {
+        let mem = backend();
+        let mut scores: Vec<(ID, f32)> = mem
+            .iter_vector_ids()
+            .map(|id| (id, q.score(&mem, id).unwrap()))
+            .collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scores.into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn combines_positives() {
+        let q = Query::new()
+            .positive(vec![1.0, 0.0, 0.0], 1.0)
+            .positive(vec![0.0, 1.0, 0.0], 1.0);
+        assert_eq!(ranked(&q)[0], 3);
+        assert_eq!(*ranked(&q).last().unwrap(), 2);
+    }
+
+    #[test]
+    fn pushes_down_negatives() {
+        let q = Query::new()
+            .positive(vec![1.0, 0.0, 0.0], 1.0)
+            .negative(vec![0.0, 1.0, 0.0], 1.0);
+        let r = ranked(&q);
+        assert_eq!(r[0], 0);
+        assert_eq!(r[3], 1);
+    }
+}
@@ -1,6 +1,8 @@
-use crate::ID;
+use anyhow::Result;
 
-#[derive(Debug)]
+use crate::{vector::dot_product, VectorBackend, ID};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct SearchResult {
     pub similarity: f32,
     pub id: ID,
@@ -12,6 +14,9 @@ pub struct ResultSet {
     ids: Vec<ID>,
     k: usize,
     pub checked: usize,
+    /// Set when the search ran out of budget before scoring every
+    /// candidate, so better matches may have been missed.
+    pub partial: bool,
 }
 
 impl ResultSet {
@@ -21,6 +26,7 @@ impl ResultSet {
             ids: Vec::with_capacity(k),
             k,
             checked: 0,
+            partial: false,
         }
     }
 
@@ -79,6 +85,84 @@ impl ResultSet {
         self.sims.truncate(self.k);
     }
 
+    /// Folds another set into this one, keeping the best `k` overall.
+    /// `map_id` translates the other set's IDs, e.g. from a shard's local
+    /// IDs to global ones.
+    pub fn merge(&mut self, other: &ResultSet, map_id: impl Fn(ID) -> ID) {
+        let checked = self.checked + other.checked;
+        for r in other.iter_results() {
+            self.add_result(map_id(r.id), r.similarity);
+        }
+        self.checked = checked;
+        self.partial |= other.partial;
+    }
+
+    /// Re-scores the best `n` results with `score`, keeping the top `k`.
+    /// The rest keep their first-pass scores, so an `n` below `k` still
+    /// fills the set.
+    pub fn rescore(
+        &self,
+        n: usize,
+        k: usize,
+        score: impl Fn(ID) -> Result<f32>,
+    ) -> Result<ResultSet> {
+        let mut out = ResultSet::new(k);
+        for (i, r) in self.iter_results().enumerate() {
+            let similarity = if i < n { score(r.id)? } else { r.similarity };
+            out.add_result(r.id, similarity);
+        }
+        out.checked = self.checked;
+        out.partial = self.partial;
+        Ok(out)
+    }
+
+    /// Picks `k` of the results with maximal marginal relevance, trading
+    /// similarity to the query against similarity to the results already
+    /// picked. `lambda` of 1.0 keeps the original top `k`; lower values
+    /// favor diversity. The returned set keeps each result's query
+    /// similarity, and like any other set is ordered by it, best first.
+    pub fn rerank_mmr(
+        &self,
+        backend: &impl VectorBackend,
+        k: usize,
+        lambda: f32,
+    ) -> Result<ResultSet> {
+        let vecs = self
+            .ids
+            .iter()
+            .map(|id| backend.get_vector(*id))
+            .collect::<Result<Vec<_>>>()?;
+        let mut picked = vec![false; self.ids.len()];
+        // The closest each candidate gets to anything picked so far.
+        let mut redundancy = vec![f32::MIN; self.ids.len()];
+        for n in 0..k.min(self.ids.len()) {
+            let mut best: Option<(usize, f32)> = None;
+            for i in (0..self.ids.len()).filter(|i| !picked[*i]) {
+                let penalty = if n == 0 { 0.0 } else { redundancy[i] };
+                let score = lambda * self.sims[i] - (1.0 - lambda) * penalty;
+                if best.is_none_or(|(_, s)| score > s) {
+                    best = Some((i, score));
+                }
+            }
+            let (chosen, _) = best.unwrap();
+            picked[chosen] = true;
+            for i in (0..self.ids.len()).filter(|i| !picked[*i]) {
+                let sim = dot_product(&vecs[i], &vecs[chosen]);
+                redundancy[i] = redundancy[i].max(sim);
+            }
+        }
+        // The results are already sorted, so keeping the picks in place
+        // keeps the set sorted too.
+        let mut out = ResultSet::new(k);
+        out.checked = self.checked;
+        out.partial = self.partial;
+        for i in (0..self.ids.len()).filter(|i| picked[*i]) {
+            out.ids.push(self.ids[i]);
+            out.sims.push(self.sims[i]);
+        }
+        Ok(out)
+    }
+
     pub fn iter_results(&self) -> impl Iterator<Item = SearchResult> + '_ {
         self.sims
             .iter()
@@ -0,0 +1,134 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// A flag that stops a running search. Clones share the flag; a child
+/// token is cancelled along with its parent but can also be cancelled on
+/// its own.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    flag: Arc<AtomicBool>,
+    parent: Option<Box<CancellationToken>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed) || self.parent.as_ref().is_some_and(|p| p.is_cancelled())
+    }
+
+    pub fn child(&self) -> Self {
+        Self {
+            flag: Arc::new(AtomicBool::new(false)),
+            parent: Some(Box::new(self.clone())),
+        }
+    }
+}
+
+/// Parameters for `VectorStore::find_nearest_with`.
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    pub k: usize,
+    pub search_k: usize,
+    pub spill: usize,
+    /// When set, re-rank a wider pool of results with maximal marginal
+    /// relevance using this lambda.
+    pub mmr_lambda: Option<f32>,
+    /// How many of the best results to re-rank for diversity. Defaults to
+    /// four times `k`.
+    pub mmr_pool: Option<usize>,
+    /// When set, re-score this many of the best candidates with the
+    /// backend's exact similarity before picking the results.
+    pub rerank_k: Option<usize>,
+    /// Stop scoring candidates once this instant has passed.
+    pub deadline: Option<Instant>,
+    /// Stop scoring after this many candidates.
+    pub max_candidates: Option<usize>,
+    /// Stop scoring once this token is cancelled.
+    pub cancel: Option<CancellationToken>,
+}
+
+impl SearchOptions {
+    pub fn new(k: usize, search_k: usize, spill: usize) -> Self {
+        Self {
+            k,
+            search_k,
+            spill,
+            mmr_lambda: None,
+            mmr_pool: None,
+            rerank_k: None,
+            deadline: None,
+            max_candidates: None,
+            cancel: None,
+        }
+    }
+
+    pub fn with_mmr(mut self, lambda: f32) -> Self {
+        self.mmr_lambda = Some(lambda);
+        self
+    }
+
+    pub fn with_mmr_pool(mut self, pool: usize) -> Self {
+        self.mmr_pool = Some(pool);
+        self
+    }
+
+    pub fn with_rerank(mut self, rerank_k: usize) -> Self {
+        self.rerank_k = Some(rerank_k);
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    pub fn with_max_candidates(mut self, max: usize) -> Self {
+        self.max_candidates = Some(max);
+        self
+    }
+
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    pub(crate) fn has_budget(&self) -> bool {
+        self.deadline.is_some() || self.max_candidates.is_some() || self.cancel.is_some()
+    }
+
+    /// True once a search that has scored `checked` candidates should stop.
+    pub(crate) fn out_of_budget(&self, checked: usize) -> bool {
+        self.max_candidates.is_some_and(|m| checked >= m)
+            || self.cancel.as_ref().is_some_and(|c| c.is_cancelled())
+            || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    /// The number of results to collect before any diversity re-ranking.
+    pub(crate) fn pool_size(&self) -> usize {
+        match self.mmr_lambda {
+            Some(_) => self.mmr_pool.unwrap_or(self.k * 4).max(self.k),
+            None => self.k,
+        }
+    }
+
+    /// The number of candidates to keep from the first scoring pass.
+    pub(crate) fn candidate_pool(&self) -> usize {
+        self.pool_size().max(self.rerank_k.unwrap_or(0))
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+
+use crate::{Bitmap, ResultSet, SearchOptions, Vector, VectorBackend, VectorStore, ID};
+
+/// How a `ShardedStore` assigns IDs to shards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShardRouting {
+    /// Spread IDs evenly by a fixed hash. Shards store the global IDs.
+    Hash,
+    /// Give each shard a run of `width` IDs, the last shard taking the
+    /// rest. Shards store IDs relative to the start of their run, so each
+    /// has its own ID space.
+    Range { width: ID },
+}
+
+/// Spreads vectors across several stores and searches them all in
+/// parallel, merging the per-shard results into one top-k.
+pub struct ShardedStore<E: VectorBackend, B: Bitmap> {
+    shards: Vec<VectorStore<E, B>>,
+    routing: ShardRouting,
+}
+
+impl<E, B> ShardedStore<E, B>
+where
+    E: VectorBackend + Send + Sync,
+    B: Bitmap + Sync,
+{
+    pub fn new(shards: Vec<VectorStore<E, B>>, routing: ShardRouting) -> Result<Self> {
+        if shards.is_empty() {
+            return Err(anyhow!("A sharded store needs at least one shard"));
+        }
+        if routing == (ShardRouting::Range { width: 0 }) {
+            return Err(anyhow!("Range width must be positive"));
+        }
+        let dims = shards[0].dimensions();
+        if shards.iter().any(|s| s.dimensions() != dims) {
+            return Err(anyhow!("Shards have different dimensions"));
+        }
+        Ok(Self { shards, routing })
+    }
+
+    pub fn shards(&self) -> &[VectorStore<E, B>] {
+        &self.shards
+    }
+
+    pub fn routing(&self) -> ShardRouting {
+        self.routing
+    }
+
+    pub fn vector_count(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|s| s.backend().info().vector_count)
+            .sum()
+    }
+
+    /// The shard an ID lives on, and its ID within that shard.
+    pub fn locate(&self, id: ID) -> (usize, ID) {
+        let n = self.shards.len();
+        match self.routing {
+            ShardRouting::Hash => ((mix(id) % n as u64) as usize, id),
+            ShardRouting::Range { width } => {
+                let shard = (id / width).min(n as u64 - 1) as usize;
+                (shard, id - shard as u64 * width)
+            }
+        }
+    }
+
+    fn global_id(&self, shard: usize, local: ID) -> ID {
+        match self.routing {
+            ShardRouting::Hash => local,
+            ShardRouting::Range { width } => local + shard as u64 * width,
+        }
+    }
+
+    pub fn add_vector(&mut self, id: ID, vector: &Vector) -> Result<()> {
+        let (shard, local) = self.locate(id);
+        self.shards[shard].add_vector(local, vector)
+    }
+
+    /// Adds the vectors, inserting into all shards in parallel.
+    pub fn add_vector_iter<'a>(
+        &mut self,
+        iter: impl Iterator<Item = (ID, &'a Vector)>,
+    ) -> Result<()> {
+        let mut batches: Vec<Vec<(ID, &Vector)>> = vec![Vec::new(); self.shards.len()];
+        for (id, v) in iter {
+            let (shard, local) = self.locate(id);
+            batches[shard].push((local, v));
+        }
+        self.shards
+            .par_iter_mut()
+            .zip(batches.into_par_iter())
+            .try_for_each(|(shard, batch)| shard.add_vector_iter(batch.into_iter()))
+    }
+
+    pub fn remove_vector(&mut self, id: ID) -> Result<()> {
+        let (shard, local) = self.locate(id);
+        self.shards[shard].remove_vector(local)
+    }
+
+    pub fn find_nearest(
+        &self,
+        target: &Vector,
+        k: usize,
+        search_k: usize,
+        spill: usize,
+    ) -> Result<ResultSet> {
+        self.gather(k, |s| s.find_nearest(target, k, search_k, spill))
+    }
+
+    /// Searches every shard with `opts`. Re-ranking, such as MMR, happens
+    /// within each shard before the merge.
+    pub fn find_nearest_with(&self, target: &Vector, opts: &SearchOptions) -> Result<ResultSet> {
+        self.gather(opts.k, |s| s.find_nearest_with(target, opts))
+    }
+
+    pub fn full_table_scan(&self, target: &Vector, k: usize) -> Result<ResultSet> {
+        self.gather(k, |s| s.full_table_scan(target, k))
+    }
+
+    fn gather(
+        &self,
+        k: usize,
+        search: impl Fn(&VectorStore<E, B>) -> Result<ResultSet> + Sync,
+    ) -> Result<ResultSet> {
+        let results = self
+            .shards
+            .par_iter()
+            .map(&search)
+            .collect::<Result<Vec<_>>>()?;
+        let mut out = ResultSet::new(k);
+        for (shard, rs) in results.iter().enumerate() {
+            out.merge(rs, |local| self.global_id(shard, local));
+        }
+        Ok(out)
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        self.shards.par_iter_mut().try_for_each(|s| s.sync())
+    }
+
+    pub fn close(self) -> Result<()> {
+        self.shards.into_par_iter().try_for_each(|s| s.close())
+    }
+}
+
+// SplitMix64's finalizer: stable across runs and platforms, unlike the
+// standard library's hashers.
+fn mix(id: ID) -> u64 {
+    let mut z = id.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
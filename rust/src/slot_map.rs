@@ -0,0 +1,205 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::ID;
+
+const PAGE_BITS: u32 = 6;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+const PAGE_MASK: ID = (PAGE_SIZE - 1) as ID;
+
+/// Up to `PAGE_SIZE` neighbouring slots. Only live values are stored,
+/// packed in offset order, so a page holding one of a sparse set of IDs
+/// costs one value rather than a full page of empty slots.
+#[derive(Clone)]
+struct Page<T> {
+    occupied: u64,
+    values: Vec<T>,
+}
+
+impl<T> Page<T> {
+    fn new() -> Self {
+        Self {
+            occupied: 0,
+            values: Vec::new(),
+        }
+    }
+
+    fn live(&self) -> usize {
+        self.values.len()
+    }
+
+    fn has(&self, off: usize) -> bool {
+        self.occupied & (1 << off) != 0
+    }
+
+    /// Where the value at `off` is, or would be, in `values`.
+    fn rank(&self, off: usize) -> usize {
+        (self.occupied & ((1 << off) - 1)).count_ones() as usize
+    }
+
+    fn get(&self, off: usize) -> Option<&T> {
+        self.has(off).then(|| &self.values[self.rank(off)])
+    }
+
+    fn insert(&mut self, off: usize, value: T) -> Option<T> {
+        let rank = self.rank(off);
+        if self.has(off) {
+            return Some(std::mem::replace(&mut self.values[rank], value));
+        }
+        // Grow from a single slot so that sparse pages stay small.
+        if self.values.len() == self.values.capacity() {
+            self.values.reserve_exact(self.values.len().max(1));
+        }
+        self.values.insert(rank, value);
+        self.occupied |= 1 << off;
+        None
+    }
+
+    fn remove(&mut self, off: usize) -> Option<T> {
+        if !self.has(off) {
+            return None;
+        }
+        self.occupied &= !(1 << off);
+        Some(self.values.remove(self.rank(off)))
+    }
+
+    fn offsets(&self) -> impl Iterator<Item = usize> {
+        let occupied = self.occupied;
+        (0..PAGE_SIZE).filter(move |off| occupied & (1 << off) != 0)
+    }
+}
+
+/// A map from IDs to values that allocates in small pages, so that memory
+/// scales with the number of live entries rather than with the largest ID,
+/// however sparse the IDs are.
+///
+/// Pages are shared between clones and copied on write, so a clone is a
+/// cheap snapshot.
+pub(crate) struct SlotMap<T> {
+    pages: HashMap<ID, Arc<Page<T>>>,
+    len: usize,
+}
+
+impl<T> Default for SlotMap<T> {
+    fn default() -> Self {
+        Self {
+            pages: HashMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> Clone for SlotMap<T> {
+    fn clone(&self) -> Self {
+        Self {
+            pages: self.pages.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T> SlotMap<T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn get(&self, id: ID) -> Option<&T> {
+        self.pages
+            .get(&(id >> PAGE_BITS))
+            .and_then(|p| p.get((id & PAGE_MASK) as usize))
+    }
+
+    pub fn contains(&self, id: ID) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Iterates over the live IDs in ascending order.
+    pub fn iter_ids(&self) -> impl Iterator<Item = ID> + '_ {
+        let mut keys: Vec<ID> = self.pages.keys().copied().collect();
+        keys.sort_unstable();
+        keys.into_iter().flat_map(move |key| {
+            self.pages[&key]
+                .offsets()
+                .map(move |off| (key << PAGE_BITS) | off as ID)
+        })
+    }
+}
+
+impl<T: Clone> SlotMap<T> {
+    pub fn insert(&mut self, id: ID, value: T) -> Option<T> {
+        let page = self
+            .pages
+            .entry(id >> PAGE_BITS)
+            .or_insert_with(|| Arc::new(Page::new()));
+        let old = Arc::make_mut(page).insert((id & PAGE_MASK) as usize, value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub fn remove(&mut self, id: ID) -> Option<T> {
+        let key = id >> PAGE_BITS;
+        let page = self.pages.get_mut(&key)?;
+        // Check before copying a shared page for a no-op.
+        if !page.has((id & PAGE_MASK) as usize) {
+            return None;
+        }
+        let page = Arc::make_mut(page);
+        let old = page.remove((id & PAGE_MASK) as usize);
+        self.len -= 1;
+        if page.live() == 0 {
+            self.pages.remove(&key);
+        }
+        old
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sparse_ids() {
+        let mut sm = SlotMap::<u32>::default();
+        let ids = [0, 63, 64, 1_000_000_000, u32::MAX as ID + 1, ID::MAX];
+        for (i, id) in ids.iter().enumerate() {
+            assert_eq!(sm.insert(*id, i as u32), None);
+        }
+        assert_eq!(sm.len(), ids.len());
+        assert_eq!(sm.pages.len(), 5);
+        // Pages only hold their live values.
+        assert_eq!(sm.pages[&0].values.capacity(), 2);
+        assert_eq!(sm.pages[&(ID::MAX >> PAGE_BITS)].values.capacity(), 1);
+        assert_eq!(sm.iter_ids().collect::<Vec<_>>(), ids);
+        assert_eq!(sm.get(1_000_000_000), Some(&3));
+        assert_eq!(sm.get(1_000_000_001), None);
+
+        assert_eq!(sm.insert(63, 10), Some(1));
+        assert_eq!(sm.len(), ids.len());
+        assert_eq!(sm.remove(ID::MAX), Some(5));
+        assert_eq!(sm.remove(ID::MAX), None);
+        assert_eq!(sm.len(), ids.len() - 1);
+        assert_eq!(sm.pages.len(), 4);
+    }
+
+    #[test]
+    fn clones_copy_on_write() {
+        let mut sm = SlotMap::<u32>::default();
+        for id in 0..200 {
+            sm.insert(id, id as u32);
+        }
+        let snap = sm.clone();
+        sm.insert(5, 500);
+        sm.remove(150);
+        sm.insert(1000, 1);
+        assert_eq!(snap.get(5), Some(&5));
+        assert_eq!(snap.get(150), Some(&150));
+        assert_eq!(snap.get(1000), None);
+        assert_eq!(snap.len(), 200);
+        assert_eq!(sm.get(5), Some(&500));
+        assert_eq!(sm.len(), 200);
+        // Untouched pages are still shared.
+        assert!(Arc::ptr_eq(&sm.pages[&1], &snap.pages[&1]));
+        assert!(!Arc::ptr_eq(&sm.pages[&0], &snap.pages[&0]));
+    }
+}
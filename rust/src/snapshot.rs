@@ -0,0 +1,38 @@
+use anyhow::Result;
+use std::ops::Deref;
+
+use crate::{Bitmap, VectorBackend, VectorStore};
+
+/// A backend that can hand out a frozen copy of itself, cheaply enough to
+/// take per query. Memory backends share their pages and `DiskBackend`
+/// shares its mapped files, each copying one only when it's written while
+/// a snapshot holds it.
+pub trait SnapshotBackend: VectorBackend {
+    type Snapshot: VectorBackend;
+
+    fn snapshot(&self) -> Result<Self::Snapshot>;
+}
+
+/// A point-in-time, read-only view of a `VectorStore`, taken with
+/// `VectorStore::snapshot`.
+///
+/// It derefs to the frozen store, so every search method is available; it
+/// can be moved to another thread and queried while the original keeps
+/// taking writes.
+pub struct Snapshot<E: VectorBackend, B: Bitmap> {
+    store: VectorStore<E, B>,
+}
+
+impl<E: VectorBackend, B: Bitmap> Snapshot<E, B> {
+    pub(crate) fn new(store: VectorStore<E, B>) -> Self {
+        Self { store }
+    }
+}
+
+impl<E: VectorBackend, B: Bitmap> Deref for Snapshot<E, B> {
+    type Target = VectorStore<E, B>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.store
+    }
+}
@@ -0,0 +1,217 @@
+#[cfg(target_arch = "x86_64")]
+use super::half_float_avx::*;
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+use super::half_float_neon::*;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use super::half_float_sse::*;
+use half::{bf16, f16};
+
+// The kernels return the dot product of the two vectors and the squared
+// norm of each, which is everything cosine similarity needs.
+pub(crate) type Sums = (f32, f32, f32);
+
+#[cfg(target_arch = "x86_64")]
+const MIN_DIM_SIZE_AVX: usize = 16;
+
+#[cfg(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    all(target_arch = "aarch64", target_feature = "neon")
+))]
+const MIN_DIM_SIZE_SIMD: usize = 8;
+
+/// An element the kernels can read: a float, or half of one.
+pub(crate) trait Lane: Copy {
+    fn to_f32(self) -> f32;
+}
+
+impl Lane for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+impl Lane for bf16 {
+    fn to_f32(self) -> f32 {
+        bf16::to_f32(self)
+    }
+}
+
+impl Lane for f16 {
+    fn to_f32(self) -> f32 {
+        f16::to_f32(self)
+    }
+}
+
+fn cosine((dot, xx, yy): Sums) -> f32 {
+    let norms = (xx * yy).sqrt();
+    if norms == 0.0 {
+        return 0.0;
+    }
+    dot / norms
+}
+
+#[cfg(target_arch = "x86_64")]
+fn has_avx() -> bool {
+    is_x86_feature_detected!("avx2")
+        && is_x86_feature_detected!("fma")
+        && is_x86_feature_detected!("f16c")
+}
+
+/// Cosine similarity between a query and a bf16 vector, converting the
+/// halves as it reads them.
+pub fn cosine_f32_bf16(x: &[f32], y: &[bf16]) -> f32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx() && x.len() >= MIN_DIM_SIZE_AVX {
+            return cosine(unsafe { sums_f32_bf16_avx(x, y) });
+        }
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("sse2") && x.len() >= MIN_DIM_SIZE_SIMD {
+            return cosine(unsafe { sums_f32_bf16_sse(x, y) });
+        }
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") && x.len() >= MIN_DIM_SIZE_SIMD {
+            return cosine(unsafe { sums_f32_bf16_neon(x, y) });
+        }
+    }
+
+    cosine(sums_non_optimized(x, y))
+}
+
+pub fn cosine_bf16(x: &[bf16], y: &[bf16]) -> f32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx() && x.len() >= MIN_DIM_SIZE_AVX {
+            return cosine(unsafe { sums_bf16_avx(x, y) });
+        }
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("sse2") && x.len() >= MIN_DIM_SIZE_SIMD {
+            return cosine(unsafe { sums_bf16_sse(x, y) });
+        }
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") && x.len() >= MIN_DIM_SIZE_SIMD {
+            return cosine(unsafe { sums_bf16_neon(x, y) });
+        }
+    }
+
+    cosine(sums_non_optimized(x, y))
+}
+
+/// Cosine similarity between a query and an f16 vector. AVX converts the
+/// halves with F16C; the SSE and NEON kernels widen them with integer ops.
+pub fn cosine_f32_f16(x: &[f32], y: &[f16]) -> f32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx() && x.len() >= MIN_DIM_SIZE_AVX {
+            return cosine(unsafe { sums_f32_f16_avx(x, y) });
+        }
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("sse2") && x.len() >= MIN_DIM_SIZE_SIMD {
+            return cosine(unsafe { sums_f32_f16_sse(x, y) });
+        }
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") && x.len() >= MIN_DIM_SIZE_SIMD {
+            return cosine(unsafe { sums_f32_f16_neon(x, y) });
+        }
+    }
+
+    cosine(sums_non_optimized(x, y))
+}
+
+pub fn cosine_f16(x: &[f16], y: &[f16]) -> f32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_avx() && x.len() >= MIN_DIM_SIZE_AVX {
+            return cosine(unsafe { sums_f16_avx(x, y) });
+        }
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("sse2") && x.len() >= MIN_DIM_SIZE_SIMD {
+            return cosine(unsafe { sums_f16_sse(x, y) });
+        }
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") && x.len() >= MIN_DIM_SIZE_SIMD {
+            return cosine(unsafe { sums_f16_neon(x, y) });
+        }
+    }
+
+    cosine(sums_non_optimized(x, y))
+}
+
+pub(crate) fn sums_non_optimized<X: Lane, Y: Lane>(x: &[X], y: &[Y]) -> Sums {
+    x.iter()
+        .zip(y.iter())
+        .fold((0.0, 0.0, 0.0), |(dot, xx, yy), (a, b)| {
+            let (a, b) = (a.to_f32(), b.to_f32());
+            (dot + a * b, xx + a * a, yy + b * b)
+        })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Kernels sum in a different order than the scalar loop, so they only
+    // agree to within rounding.
+    pub(crate) fn assert_sums_close(simd: Sums, scalar: Sums) {
+        for (a, b) in [(simd.0, scalar.0), (simd.1, scalar.1), (simd.2, scalar.2)] {
+            assert!((a - b).abs() <= 1e-4 * b.abs().max(1.0), "{} != {}", a, b);
+        }
+    }
+
+    // Odd lengths, so the kernels' tails get exercised too.
+    pub(crate) fn test_vectors() -> Vec<(Vec<f32>, Vec<f32>)> {
+        [8, 16, 37, 100]
+            .into_iter()
+            .map(|n| {
+                (
+                    crate::create_random_vector(n),
+                    crate::create_random_vector(n),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn half_cosine_matches_full_precision() {
+        for (x, y) in test_vectors() {
+            let expected = crate::vector::cosine_similarity(&x, &y);
+            let (bx, by): (Vec<bf16>, Vec<bf16>) = (
+                x.iter().map(|f| bf16::from_f32(*f)).collect(),
+                y.iter().map(|f| bf16::from_f32(*f)).collect(),
+            );
+            let (hx, hy): (Vec<f16>, Vec<f16>) = (
+                x.iter().map(|f| f16::from_f32(*f)).collect(),
+                y.iter().map(|f| f16::from_f32(*f)).collect(),
+            );
+            assert!((cosine_f32_bf16(&x, &by) - expected).abs() < 2e-2);
+            assert!((cosine_bf16(&bx, &by) - expected).abs() < 2e-2);
+            assert!((cosine_f32_f16(&x, &hy) - expected).abs() < 2e-3);
+            assert!((cosine_f16(&hx, &hy) - expected).abs() < 2e-3);
+        }
+    }
+}
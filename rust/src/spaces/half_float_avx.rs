@@ -0,0 +1,93 @@
+use half::{bf16, f16};
+use std::arch::x86_64::*;
+
+use super::half_float::{sums_non_optimized, Sums};
+use super::simple_avx::hsum256_ps_avx;
+
+#[target_feature(enable = "avx")]
+#[inline]
+unsafe fn load_f32(ptr: *const f32) -> __m256 {
+    _mm256_loadu_ps(ptr)
+}
+
+// A bf16 is the top half of an f32, so widen it and shift it into place.
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn load_bf16(ptr: *const bf16) -> __m256 {
+    let halves = _mm_loadu_si128(ptr as *const __m128i);
+    _mm256_castsi256_ps(_mm256_slli_epi32(_mm256_cvtepu16_epi32(halves), 16))
+}
+
+#[target_feature(enable = "avx")]
+#[target_feature(enable = "f16c")]
+#[inline]
+unsafe fn load_f16(ptr: *const f16) -> __m256 {
+    _mm256_cvtph_ps(_mm_loadu_si128(ptr as *const __m128i))
+}
+
+// Reads eight lanes of each side at a time, converting them to f32 in
+// registers, and leaves the tail to the scalar loop.
+macro_rules! sums_avx {
+    ($name:ident, $x:ty, $load_x:ident, $y:ty, $load_y:ident) => {
+        #[target_feature(enable = "avx2")]
+        #[target_feature(enable = "fma")]
+        #[target_feature(enable = "f16c")]
+        pub(crate) unsafe fn $name(x: &[$x], y: &[$y]) -> Sums {
+            let n = x.len().min(y.len());
+            let m = n - (n % 8);
+            let (ptr1, ptr2) = (x.as_ptr(), y.as_ptr());
+            let mut dot: __m256 = _mm256_setzero_ps();
+            let mut xx: __m256 = _mm256_setzero_ps();
+            let mut yy: __m256 = _mm256_setzero_ps();
+            let mut i: usize = 0;
+            while i < m {
+                let a = $load_x(ptr1.add(i));
+                let b = $load_y(ptr2.add(i));
+                dot = _mm256_fmadd_ps(a, b, dot);
+                xx = _mm256_fmadd_ps(a, a, xx);
+                yy = _mm256_fmadd_ps(b, b, yy);
+                i += 8;
+            }
+            let (dot_tail, xx_tail, yy_tail) = sums_non_optimized(&x[m..n], &y[m..n]);
+            (
+                hsum256_ps_avx(dot) + dot_tail,
+                hsum256_ps_avx(xx) + xx_tail,
+                hsum256_ps_avx(yy) + yy_tail,
+            )
+        }
+    };
+}
+
+sums_avx!(sums_f32_bf16_avx, f32, load_f32, bf16, load_bf16);
+sums_avx!(sums_bf16_avx, bf16, load_bf16, bf16, load_bf16);
+sums_avx!(sums_f32_f16_avx, f32, load_f32, f16, load_f16);
+sums_avx!(sums_f16_avx, f16, load_f16, f16, load_f16);
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_half_spaces_avx() {
+        use super::*;
+        use crate::spaces::half_float::tests::*;
+
+        if is_x86_feature_detected!("avx2")
+            && is_x86_feature_detected!("fma")
+            && is_x86_feature_detected!("f16c")
+        {
+            for (x, y) in test_vectors() {
+                let bx: Vec<bf16> = x.iter().map(|f| bf16::from_f32(*f)).collect();
+                let by: Vec<bf16> = y.iter().map(|f| bf16::from_f32(*f)).collect();
+                let hx: Vec<f16> = x.iter().map(|f| f16::from_f32(*f)).collect();
+                let hy: Vec<f16> = y.iter().map(|f| f16::from_f32(*f)).collect();
+                unsafe {
+                    assert_sums_close(sums_f32_bf16_avx(&x, &by), sums_non_optimized(&x, &by));
+                    assert_sums_close(sums_bf16_avx(&bx, &by), sums_non_optimized(&bx, &by));
+                    assert_sums_close(sums_f32_f16_avx(&x, &hy), sums_non_optimized(&x, &hy));
+                    assert_sums_close(sums_f16_avx(&hx, &hy), sums_non_optimized(&hx, &hy));
+                }
+            }
+        } else {
+            println!("avx half test skipped");
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use half::{bf16, f16};
+#[cfg(target_feature = "neon")]
+use std::arch::aarch64::*;
+use std::ptr::read_unaligned;
+
+use super::half_float::{sums_non_optimized, Sums};
+
+#[cfg(target_feature = "neon")]
+#[inline]
+unsafe fn load_f32(ptr: *const f32) -> float32x4_t {
+    vld1q_f32(read_unaligned(ptr as *const [f32; 4]).as_ptr())
+}
+
+// A bf16 is the top half of an f32, so widen it with a shift into place.
+#[cfg(target_feature = "neon")]
+#[inline]
+unsafe fn load_bf16(ptr: *const bf16) -> float32x4_t {
+    let halves = vld1_u16(read_unaligned(ptr as *const [u16; 4]).as_ptr());
+    vreinterpretq_f32_u32(vshll_n_u16::<16>(halves))
+}
+
+// Stable Rust has no NEON f16 vector types yet, so widen f16 with integer
+// ops, as the SSE kernel does: move the exponent and mantissa into place
+// and rebias the exponent, then patch up infinities and NaNs, and
+// renormalize subnormals by subtracting the smallest normal half.
+#[cfg(target_feature = "neon")]
+#[inline]
+unsafe fn load_f16(ptr: *const f16) -> float32x4_t {
+    const EXP: u32 = 0x7c00 << 13;
+    let h = vmovl_u16(vld1_u16(read_unaligned(ptr as *const [u16; 4]).as_ptr()));
+    let o = vshlq_n_u32::<13>(vandq_u32(h, vdupq_n_u32(0x7fff)));
+    let exp = vandq_u32(o, vdupq_n_u32(EXP));
+    let o = vaddq_u32(o, vdupq_n_u32((127 - 15) << 23));
+    let inf_nan = vceqq_u32(exp, vdupq_n_u32(EXP));
+    let o = vaddq_u32(o, vandq_u32(inf_nan, vdupq_n_u32((128 - 16) << 23)));
+    let small = vceqq_u32(exp, vdupq_n_u32(0));
+    let renormed = vreinterpretq_u32_f32(vsubq_f32(
+        vreinterpretq_f32_u32(vaddq_u32(o, vdupq_n_u32(1 << 23))),
+        vdupq_n_f32(f32::from_bits(113 << 23)),
+    ));
+    let o = vbslq_u32(small, renormed, o);
+    let sign = vshlq_n_u32::<16>(vandq_u32(h, vdupq_n_u32(0x8000)));
+    vreinterpretq_f32_u32(vorrq_u32(o, sign))
+}
+
+macro_rules! sums_neon {
+    ($name:ident, $x:ty, $load_x:ident, $y:ty, $load_y:ident) => {
+        #[cfg(target_feature = "neon")]
+        pub(crate) unsafe fn $name(x: &[$x], y: &[$y]) -> Sums {
+            let n = x.len().min(y.len());
+            let m = n - (n % 4);
+            let (ptr1, ptr2) = (x.as_ptr(), y.as_ptr());
+            let mut dot = vdupq_n_f32(0.);
+            let mut xx = vdupq_n_f32(0.);
+            let mut yy = vdupq_n_f32(0.);
+            let mut i: usize = 0;
+            while i < m {
+                let a = $load_x(ptr1.add(i));
+                let b = $load_y(ptr2.add(i));
+                dot = vfmaq_f32(dot, a, b);
+                xx = vfmaq_f32(xx, a, a);
+                yy = vfmaq_f32(yy, b, b);
+                i += 4;
+            }
+            let (dot_tail, xx_tail, yy_tail) = sums_non_optimized(&x[m..n], &y[m..n]);
+            (
+                vaddvq_f32(dot) + dot_tail,
+                vaddvq_f32(xx) + xx_tail,
+                vaddvq_f32(yy) + yy_tail,
+            )
+        }
+    };
+}
+
+sums_neon!(sums_f32_bf16_neon, f32, load_f32, bf16, load_bf16);
+sums_neon!(sums_bf16_neon, bf16, load_bf16, bf16, load_bf16);
+sums_neon!(sums_f32_f16_neon, f32, load_f32, f16, load_f16);
+sums_neon!(sums_f16_neon, f16, load_f16, f16, load_f16);
+
+#[cfg(test)]
+mod tests {
+    #[cfg(target_feature = "neon")]
+    #[test]
+    fn test_half_spaces_neon() {
+        use super::*;
+        use crate::spaces::half_float::tests::*;
+
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            for (x, y) in test_vectors() {
+                let bx: Vec<bf16> = x.iter().map(|f| bf16::from_f32(*f)).collect();
+                let by: Vec<bf16> = y.iter().map(|f| bf16::from_f32(*f)).collect();
+                let hx: Vec<f16> = x.iter().map(|f| f16::from_f32(*f)).collect();
+                let hy: Vec<f16> = y.iter().map(|f| f16::from_f32(*f)).collect();
+                unsafe {
+                    assert_sums_close(sums_f32_bf16_neon(&x, &by), sums_non_optimized(&x, &by));
+                    assert_sums_close(sums_bf16_neon(&bx, &by), sums_non_optimized(&bx, &by));
+                    assert_sums_close(sums_f32_f16_neon(&x, &hy), sums_non_optimized(&x, &hy));
+                    assert_sums_close(sums_f16_neon(&hx, &hy), sums_non_optimized(&hx, &hy));
+                }
+            }
+            // Every half widens exactly, subnormals and infinities too.
+            let all: Vec<f16> = (0..=u16::MAX).map(f16::from_bits).collect();
+            for chunk in all.chunks(4) {
+                let mut out = [0.0f32; 4];
+                unsafe { vst1q_f32(out.as_mut_ptr(), load_f16(chunk.as_ptr())) };
+                for (h, f) in chunk.iter().zip(out) {
+                    assert!(h.is_nan() && f.is_nan() || h.to_f32().to_bits() == f.to_bits());
+                }
+            }
+        } else {
+            println!("neon half test skipped");
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use half::{bf16, f16};
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use super::half_float::{sums_non_optimized, Sums};
+use super::simple_sse::hsum128_ps_sse;
+
+#[target_feature(enable = "sse")]
+#[inline]
+unsafe fn load_f32(ptr: *const f32) -> __m128 {
+    _mm_loadu_ps(ptr)
+}
+
+// A bf16 is the top half of an f32, so interleave it above zeroes.
+#[target_feature(enable = "sse2")]
+#[inline]
+unsafe fn load_bf16(ptr: *const bf16) -> __m128 {
+    let halves = _mm_loadl_epi64(ptr as *const __m128i);
+    _mm_castsi128_ps(_mm_unpacklo_epi16(_mm_setzero_si128(), halves))
+}
+
+// Without F16C, widen f16 with integer ops: move the exponent and mantissa
+// into place and rebias the exponent, then patch up infinities and NaNs,
+// and renormalize subnormals by subtracting the smallest normal half.
+// Exact for every f16.
+#[target_feature(enable = "sse2")]
+#[inline]
+unsafe fn load_f16(ptr: *const f16) -> __m128 {
+    const EXP: i32 = 0x7c00 << 13;
+    let zero = _mm_setzero_si128();
+    let h = _mm_unpacklo_epi16(_mm_loadl_epi64(ptr as *const __m128i), zero);
+    let o = _mm_slli_epi32(_mm_and_si128(h, _mm_set1_epi32(0x7fff)), 13);
+    let exp = _mm_and_si128(o, _mm_set1_epi32(EXP));
+    let o = _mm_add_epi32(o, _mm_set1_epi32((127 - 15) << 23));
+    let inf_nan = _mm_cmpeq_epi32(exp, _mm_set1_epi32(EXP));
+    let o = _mm_add_epi32(o, _mm_and_si128(inf_nan, _mm_set1_epi32((128 - 16) << 23)));
+    let small = _mm_cmpeq_epi32(exp, zero);
+    let renormed = _mm_castps_si128(_mm_sub_ps(
+        _mm_castsi128_ps(_mm_add_epi32(o, _mm_set1_epi32(1 << 23))),
+        _mm_castsi128_ps(_mm_set1_epi32(113 << 23)),
+    ));
+    let o = _mm_or_si128(_mm_and_si128(small, renormed), _mm_andnot_si128(small, o));
+    let sign = _mm_slli_epi32(_mm_and_si128(h, _mm_set1_epi32(0x8000)), 16);
+    _mm_castsi128_ps(_mm_or_si128(o, sign))
+}
+
+macro_rules! sums_sse {
+    ($name:ident, $x:ty, $load_x:ident, $y:ty, $load_y:ident) => {
+        #[target_feature(enable = "sse2")]
+        pub(crate) unsafe fn $name(x: &[$x], y: &[$y]) -> Sums {
+            let n = x.len().min(y.len());
+            let m = n - (n % 4);
+            let (ptr1, ptr2) = (x.as_ptr(), y.as_ptr());
+            let mut dot: __m128 = _mm_setzero_ps();
+            let mut xx: __m128 = _mm_setzero_ps();
+            let mut yy: __m128 = _mm_setzero_ps();
+            let mut i: usize = 0;
+            while i < m {
+                let a = $load_x(ptr1.add(i));
+                let b = $load_y(ptr2.add(i));
+                dot = _mm_add_ps(_mm_mul_ps(a, b), dot);
+                xx = _mm_add_ps(_mm_mul_ps(a, a), xx);
+                yy = _mm_add_ps(_mm_mul_ps(b, b), yy);
+                i += 4;
+            }
+            let (dot_tail, xx_tail, yy_tail) = sums_non_optimized(&x[m..n], &y[m..n]);
+            (
+                hsum128_ps_sse(dot) + dot_tail,
+                hsum128_ps_sse(xx) + xx_tail,
+                hsum128_ps_sse(yy) + yy_tail,
+            )
+        }
+    };
+}
+
+sums_sse!(sums_f32_bf16_sse, f32, load_f32, bf16, load_bf16);
+sums_sse!(sums_bf16_sse, bf16, load_bf16, bf16, load_bf16);
+sums_sse!(sums_f32_f16_sse, f32, load_f32, f16, load_f16);
+sums_sse!(sums_f16_sse, f16, load_f16, f16, load_f16);
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_half_spaces_sse() {
+        use super::*;
+        use crate::spaces::half_float::tests::*;
+
+        if is_x86_feature_detected!("sse2") {
+            for (x, y) in test_vectors() {
+                let bx: Vec<bf16> = x.iter().map(|f| bf16::from_f32(*f)).collect();
+                let by: Vec<bf16> = y.iter().map(|f| bf16::from_f32(*f)).collect();
+                let hx: Vec<f16> = x.iter().map(|f| f16::from_f32(*f)).collect();
+                let hy: Vec<f16> = y.iter().map(|f| f16::from_f32(*f)).collect();
+                unsafe {
+                    assert_sums_close(sums_f32_bf16_sse(&x, &by), sums_non_optimized(&x, &by));
+                    assert_sums_close(sums_bf16_sse(&bx, &by), sums_non_optimized(&bx, &by));
+                    assert_sums_close(sums_f32_f16_sse(&x, &hy), sums_non_optimized(&x, &hy));
+                    assert_sums_close(sums_f16_sse(&hx, &hy), sums_non_optimized(&hx, &hy));
+                }
+            }
+            // Every half widens exactly, subnormals and infinities too.
+            let all: Vec<f16> = (0..=u16::MAX).map(f16::from_bits).collect();
+            for chunk in all.chunks(4) {
+                let mut out = [0.0f32; 4];
+                unsafe { _mm_storeu_ps(out.as_mut_ptr(), load_f16(chunk.as_ptr())) };
+                for (h, f) in chunk.iter().zip(out) {
+                    assert!(h.is_nan() && f.is_nan() || h.to_f32().to_bits() == f.to_bits());
+                }
+            }
+        } else {
+            println!("sse half test skipped");
+        }
+    }
+}
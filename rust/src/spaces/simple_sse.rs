@@ -7,7 +7,7 @@ use std::ptr::read_unaligned;
 use crate::unaligned_f32::UnalignedF32Slice;
 
 #[target_feature(enable = "sse")]
-unsafe fn hsum128_ps_sse(x: __m128) -> f32 {
+pub(crate) unsafe fn hsum128_ps_sse(x: __m128) -> f32 {
     let x64: __m128 = _mm_add_ps(x, _mm_movehl_ps(x, x));
     let x32: __m128 = _mm_add_ss(x64, _mm_shuffle_ps(x64, x64, 0x55));
     _mm_cvtss_f32(x32)
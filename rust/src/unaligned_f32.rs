@@ -16,8 +16,8 @@ pub struct UnalignedF32Slice([u8]);
 impl UnalignedF32Slice {
     /// Creates an unaligned slice of f32 wrapper from a slice of bytes.
     pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<&Self> {
-        if bytes.len() % size_of::<f32>() == 0 {
-            Ok(unsafe { transmute(bytes) })
+        if bytes.len().is_multiple_of(size_of::<f32>()) {
+            Ok(unsafe { transmute::<&[u8], &Self>(bytes) })
         } else {
             Err(anyhow::anyhow!("Byte size mismatch to f32"))
         }
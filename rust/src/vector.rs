@@ -26,3 +26,13 @@ pub fn distance(vec: &Vector, other: &Vector) -> f32 {
         .fold(0.0, |acc, (a, b)| acc + ((a - b) * (a - b)))
         .sqrt()
 }
+
+/// Cosine similarity, so that larger is closer. Zero vectors are similar to nothing.
+#[inline(always)]
+pub fn cosine_similarity(vec: &Vector, other: &Vector) -> f32 {
+    let norms = (dot_product(vec, vec) * dot_product(other, other)).sqrt();
+    if norms == 0.0 {
+        return 0.0;
+    }
+    dot_product(vec, other) / norms
+}
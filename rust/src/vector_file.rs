@@ -1,63 +1,166 @@
 use anyhow::{anyhow, Result};
 use memmap2::MmapMut;
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use crate::quantization::Quantization;
+use crate::{quantization::Quantization, Vector};
 
+/// A fixed number of vector slots in a mapped file. The file starts with
+/// an occupancy bitmap, one bit per slot, so a slot's bytes can be
+/// anything a quantizer writes, zeroes included.
 pub struct VectorFile<Q: Quantization> {
-    dimensions: usize,
+    path: PathBuf,
+    // Set once the file is replaced by a copy; it's deleted on drop.
+    retired: AtomicBool,
     vec_size: usize,
     mmap: MmapMut,
     max_vecs: usize,
+    // The length of the bitmap, padded so slots keep the mapping's alignment.
+    header: usize,
+    count: usize,
     quantization: std::marker::PhantomData<Q>,
 }
 
 impl<Q: Quantization> VectorFile<Q> {
-    pub fn create_or_open(path: PathBuf, dimensions: usize, max_vecs: usize) -> Result<Self> {
-        let file = match std::fs::OpenOptions::new()
+    pub fn create_or_open(path: PathBuf, vec_size: usize, max_vecs: usize) -> Result<Self> {
+        let file = std::fs::OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .truncate(true)
-            .open(&path)
-        {
-            Ok(f) => f,
-            Err(e) => panic!("Failed to open or create file: {}", e),
-        };
-        let vec_size = Q::vector_size(dimensions);
-
-        let file_size = max_vecs * vec_size;
-        if file.metadata().unwrap().len() == 0 {
-            file.set_len(file_size as u64).unwrap();
+            .truncate(false)
+            .open(&path)?;
+        let header = max_vecs.div_ceil(8).next_multiple_of(64);
+        let file_size = header + max_vecs * vec_size;
+        if file.metadata()?.len() == 0 {
+            file.set_len(file_size as u64)?;
             file.sync_data()?;
         }
         let mmap = unsafe { MmapMut::map_mut(&file)? };
+        if mmap.len() != file_size {
+            return Err(anyhow!("Vector file {} has the wrong size", path.display()));
+        }
+        let count = mmap[..header].iter().map(|b| b.count_ones() as usize).sum();
         Ok(Self {
-            dimensions,
+            path,
+            retired: AtomicBool::new(false),
             vec_size,
             mmap,
             max_vecs,
+            header,
+            count,
             quantization: Default::default(),
         })
     }
 
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Marks the file as replaced, so it's deleted once the last reader
+    /// drops it.
+    pub fn retire(&self) {
+        self.retired.store(true, Ordering::Relaxed);
+    }
+
     pub fn flush(&self) -> Result<()> {
         Ok(self.mmap.flush_async()?)
     }
 
-    pub fn write_at(&mut self, offset: usize, vec: &Q::Lower) -> Result<()> {
+    pub fn write_at(&mut self, q: &Q, offset: usize, vec: &Q::Lower) -> Result<()> {
+        let slice = self.slice_mut(offset)?;
+        q.marshal(vec, slice)?;
+        if !self.exists_at(offset) {
+            self.mmap[offset / 8] |= 1 << (offset % 8);
+            self.count += 1;
+        }
+        Ok(())
+    }
+
+    pub fn read_at(&self, q: &Q, offset: usize) -> Result<Q::Lower> {
+        q.unmarshal(self.slice(offset)?)
+    }
+
+    /// Scores a query against the vector at `offset` straight from the
+    /// mapped bytes.
+    pub fn compare_at(&self, q: &Q, offset: usize, target: &Vector) -> Result<f32> {
+        q.compare_bytes(target, self.slice(offset)?)
+    }
+
+    /// The bytes of the vector at `offset`, as the quantizer marshaled it.
+    pub fn bytes_at(&self, offset: usize) -> Result<&[u8]> {
+        self.slice(offset)
+    }
+
+    pub fn exists_at(&self, offset: usize) -> bool {
+        offset < self.max_vecs && self.mmap[offset / 8] & (1 << (offset % 8)) != 0
+    }
+
+    pub fn clear_at(&mut self, offset: usize) -> Result<()> {
+        if self.exists_at(offset) {
+            self.mmap[offset / 8] &= !(1 << (offset % 8));
+            self.count -= 1;
+        }
+        self.slice_mut(offset)?.fill(0);
+        Ok(())
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    fn slice(&self, offset: usize) -> Result<&[u8]> {
         if offset >= self.max_vecs {
             return Err(anyhow!("Offset outside file bounds"));
         }
-        let slice = &mut self.mmap[offset * self.vec_size..(offset + 1) * self.vec_size];
-        Q::marshal(vec, slice)
+        let start = self.header + offset * self.vec_size;
+        Ok(&self.mmap[start..start + self.vec_size])
     }
 
-    pub fn read_at(&self, offset: usize) -> Result<Q::Lower> {
+    fn slice_mut(&mut self, offset: usize) -> Result<&mut [u8]> {
         if offset >= self.max_vecs {
             return Err(anyhow!("Offset outside file bounds"));
         }
-        let slice = &self.mmap[offset * self.vec_size..(offset + 1) * self.vec_size];
-        Q::unmarshal(slice)
+        let start = self.header + offset * self.vec_size;
+        Ok(&mut self.mmap[start..start + self.vec_size])
+    }
+}
+
+impl<Q: Quantization> Drop for VectorFile<Q> {
+    fn drop(&mut self) {
+        if self.retired.load(Ordering::Relaxed) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::NoQuantization;
+
+    #[test]
+    fn zeroed_vectors_are_occupied() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("0.vec");
+        let q = NoQuantization::default();
+        let mut vf = VectorFile::<NoQuantization>::create_or_open(path.clone(), 8, 100)?;
+        vf.write_at(&q, 3, &vec![0.0, 0.0])?;
+        vf.write_at(&q, 99, &vec![1.0, 0.0])?;
+        vf.write_at(&q, 99, &vec![0.0, 1.0])?;
+        assert!(vf.exists_at(3));
+        assert!(!vf.exists_at(4));
+        assert_eq!(vf.count(), 2);
+        vf.flush()?;
+        drop(vf);
+
+        let mut vf = VectorFile::<NoQuantization>::create_or_open(path, 8, 100)?;
+        assert_eq!(vf.count(), 2);
+        assert_eq!(vf.read_at(&q, 99)?, vec![0.0, 1.0]);
+        vf.clear_at(3)?;
+        assert!(!vf.exists_at(3));
+        assert_eq!(vf.count(), 1);
+        Ok(())
     }
 }
@@ -1,11 +1,25 @@
 use anyhow::{anyhow, Result};
 use argminmax::ArgMinMax;
-use std::{borrow::BorrowMut, collections::HashMap};
+use std::{
+    borrow::BorrowMut,
+    collections::HashMap,
+    path::PathBuf,
+    sync::{mpsc::Receiver, Arc},
+    time::{Duration, SystemTime},
+};
 
 use crate::{
     backend::VectorBackend,
+    best_effort_cursor::BestEffortCursor,
+    change_log::{Change, ChangeEvent, ChangeLog},
     counting_bitmap::CountingBitmap,
     create_random_vector,
+    expiry::{to_millis, ExpiryMap},
+    grouped::{self, Group},
+    payload::{Payload, PayloadStore},
+    query::Query,
+    search_options::SearchOptions,
+    snapshot::{Snapshot, SnapshotBackend},
     vector::{dot_product, normalize},
     Basis, Bitmap, ResultSet, Vector, ID,
 };
@@ -13,11 +27,27 @@ use crate::{
 pub struct VectorStore<E: VectorBackend, B: Bitmap> {
     backend: E,
     dimensions: usize,
-    bases: Vec<Basis>,
+    bases: Arc<Vec<Basis>>,
     // If we ever have more than INT_MAX_32 dimensions, I quit.
-    bitmaps: Vec<HashMap<i32, B>>,
+    // Bitmaps are shared with snapshots and copied on write.
+    bitmaps: Vec<HashMap<i32, Arc<B>>>,
+    bases_dirty: bool,
+    expiry: ExpiryMap,
+    expiry_dirty: bool,
+    // Shared with snapshots and copied on write, like the bitmaps.
+    payloads: Arc<PayloadStore<B>>,
+    payloads_dirty: bool,
+    change_log: Option<ChangeLog>,
+    // Set on snapshots, so TTLs run out as of when it was taken.
+    frozen_at: Option<SystemTime>,
 }
 
+const EXPIRY_BLOB: &str = "expiry";
+const PAYLOADS_BLOB: &str = "payloads";
+
+// A vector, its expiry and its payload as they were before a change.
+type Undo = (Option<Vector>, Option<SystemTime>, Option<Payload>);
+
 impl<E: VectorBackend> VectorStore<E, crate::bitmaps::CRoaringBitmap> {
     pub fn new(backend: E) -> Result<Self> {
         VectorStore::new_vector_store(backend)
@@ -34,6 +64,18 @@ impl<E: VectorBackend> VectorStore<E, crate::bitmaps::RoaringBitmap> {
     }
 }
 
+impl<E: VectorBackend> VectorStore<E, crate::bitmaps::CRoaringTreemap> {
+    pub fn new_croaring_treemap(backend: E) -> Result<Self> {
+        VectorStore::new_vector_store(backend)
+    }
+}
+
+impl<E: VectorBackend> VectorStore<E, crate::bitmaps::RoaringTreemap> {
+    pub fn new_roaring_treemap(backend: E) -> Result<Self> {
+        VectorStore::new_vector_store(backend)
+    }
+}
+
 impl<E: VectorBackend> VectorStore<E, crate::bitmaps::BitVec> {
     pub fn new_bitvec_bitmap(backend: E) -> Result<Self> {
         VectorStore::new_vector_store(backend)
@@ -41,22 +83,117 @@ impl<E: VectorBackend> VectorStore<E, crate::bitmaps::BitVec> {
 }
 
 impl<E: VectorBackend, B: Bitmap> VectorStore<E, B> {
-    pub fn new_vector_store(mut backend: E) -> Result<Self> {
+    pub fn new_vector_store(backend: E) -> Result<Self> {
+        Self::open_with_bases(backend, None)
+    }
+
+    /// Creates a store that projects with the given bases, such as those of
+    /// another shard, so that the two can later be merged without
+    /// reprojecting. A backend that already has bases must have these.
+    pub fn new_vector_store_with_bases(backend: E, bases: Vec<Basis>) -> Result<Self> {
+        Self::open_with_bases(backend, Some(bases))
+    }
+
+    fn open_with_bases(mut backend: E, want: Option<Vec<Basis>>) -> Result<Self> {
         let info = backend.info();
-        let bases = match backend.load_bases()? {
-            Some(b) => b,
-            None => make_basis(info.n_basis, info.dimensions)?,
+        let (bases, bases_dirty) = match (backend.load_bases()?, want) {
+            (Some(b), Some(w)) if b != w => {
+                return Err(anyhow!("Store already has different bases"));
+            }
+            (Some(b), _) => (b, false),
+            (None, Some(w)) => {
+                check_bases(&w, info.n_basis, info.dimensions)?;
+                (w, true)
+            }
+            (None, None) => (make_basis(info.n_basis, info.dimensions)?, true),
         };
         let bitmaps = load_all_bitmaps(backend.borrow_mut())?;
+        let expiry = match backend.load_blob(EXPIRY_BLOB)? {
+            Some(data) => ExpiryMap::from_bytes(&data)?,
+            None => ExpiryMap::default(),
+        };
+        let payloads = match backend.load_blob(PAYLOADS_BLOB)? {
+            Some(data) => PayloadStore::from_bytes(&data)?,
+            None => PayloadStore::new(),
+        };
         let out = Self {
             backend,
             dimensions: info.dimensions,
-            bases,
+            bases: Arc::new(bases),
             bitmaps,
+            bases_dirty,
+            expiry,
+            expiry_dirty: false,
+            payloads: Arc::new(payloads),
+            payloads_dirty: false,
+            change_log: None,
+            frozen_at: None,
         };
         Ok(out)
     }
 
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    pub fn bases(&self) -> &[Basis] {
+        &self.bases
+    }
+
+    /// Replaces the bases, reprojecting every stored vector into new
+    /// bitmaps.
+    pub fn set_bases(&mut self, bases: Vec<Basis>) -> Result<()> {
+        check_bases(&bases, self.bases.len(), self.dimensions)?;
+        if *self.bases == bases {
+            return Ok(());
+        }
+        let old = (self.bases.clone(), self.bitmaps.clone(), self.bases_dirty);
+        self.bases = Arc::new(bases);
+        self.bases_dirty = true;
+        // Every face is kept, even empty, so sync overwrites the old ones.
+        self.bitmaps = (0..self.bases.len())
+            .map(|_| {
+                (1..=self.dimensions as i32)
+                    .flat_map(|x| [x, -x])
+                    .map(|face| (face, Arc::default()))
+                    .collect()
+            })
+            .collect();
+        let ids: Vec<ID> = self.backend.iter_vector_ids().collect();
+        for id in ids {
+            let v = self.backend.get_vector(id)?;
+            self.add_to_bitmaps(id, &v)?;
+        }
+        let bases = self.bases.clone();
+        let logged = self.log_change(|| ChangeEvent::BasesChanged {
+            bases: bases.to_vec(),
+        });
+        if logged.is_err() {
+            (self.bases, self.bitmaps, self.bases_dirty) = old;
+        }
+        logged
+    }
+
+    pub fn backend(&self) -> &E {
+        &self.backend
+    }
+
+    pub(crate) fn backend_mut(&mut self) -> &mut E {
+        &mut self.backend
+    }
+
+    /// Estimates the in-memory size of the bases and bitmaps, in bytes.
+    pub fn index_size(&self) -> usize {
+        let bases = self.bases.len() * self.dimensions * self.dimensions * 4;
+        let bitmaps: usize = self
+            .bitmaps
+            .iter()
+            .flat_map(|faces| faces.values())
+            .map(|bm| bm.estimate_size())
+            .sum();
+        bases + bitmaps
+    }
+
     #[inline(always)]
     pub fn add_vector(&mut self, id: ID, vector: &Vector) -> Result<()> {
         self.add_vector_iter(vec![(id, vector)].into_iter())
@@ -67,12 +204,233 @@ impl<E: VectorBackend, B: Bitmap> VectorStore<E, B> {
         iter: impl Iterator<Item = (ID, &'a Vector)>,
     ) -> Result<()> {
         for (id, vec) in iter {
+            if id > B::MAX_ID {
+                return Err(anyhow!("ID {} is out of range for this bitmap type", id));
+            }
+            // Put first, so a failed put leaves an existing vector indexed.
+            let existed = self.backend.vector_exists(id);
+            let old = self.before_logged_change(id, existed)?;
             self.backend.put_vector(id, vec)?;
+            if existed {
+                self.remove_from_bitmaps(id);
+            }
+            // Re-adding a vector without a TTL keeps it for good.
+            self.expiry_dirty |= self.expiry.clear(id);
             self.add_to_bitmaps(id, vec)?;
+            let logged = self.log_change(|| ChangeEvent::Put {
+                id,
+                vector: vec.clone(),
+            });
+            if logged.is_err() {
+                self.restore(id, old)?;
+                return logged;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a vector that expires `ttl` from now.
+    pub fn add_vector_with_ttl(&mut self, id: ID, vector: &Vector, ttl: Duration) -> Result<()> {
+        self.add_vector_with_expiry(id, vector, SystemTime::now() + ttl)
+    }
+
+    /// Adds a vector that is hidden from searches from `at` onwards, and
+    /// removed by the next `expire`.
+    pub fn add_vector_with_expiry(
+        &mut self,
+        id: ID,
+        vector: &Vector,
+        at: SystemTime,
+    ) -> Result<()> {
+        self.add_vector(id, vector)?;
+        self.set_expiry(id, at)
+    }
+
+    pub(crate) fn set_expiry(&mut self, id: ID, at: SystemTime) -> Result<()> {
+        let old = self.expiry.get(id);
+        self.expiry.set(id, at);
+        self.expiry_dirty = true;
+        let logged = self.log_change(|| ChangeEvent::SetExpiry {
+            id,
+            at: to_millis(at),
+        });
+        if logged.is_err() {
+            match old {
+                Some(old) => self.expiry.set(id, old),
+                None => _ = self.expiry.clear(id),
+            }
+        }
+        logged
+    }
+
+    pub fn expires_at(&self, id: ID) -> Option<SystemTime> {
+        self.expiry.get(id)
+    }
+
+    /// Attaches a payload to a stored vector, replacing any it had, for
+    /// filtering with `payloads().compile`. It's dropped along with the
+    /// vector.
+    pub fn set_payload(&mut self, id: ID, payload: Payload) -> Result<()> {
+        if !self.backend.vector_exists(id) {
+            return Err(anyhow!("No vector present"));
+        }
+        Arc::make_mut(&mut self.payloads).set(id, payload)?;
+        self.payloads_dirty = true;
+        Ok(())
+    }
+
+    pub fn payloads(&self) -> &PayloadStore<B> {
+        &self.payloads
+    }
+
+    // Drops the payload of a removed vector.
+    fn clear_payload(&mut self, id: ID) {
+        if self.payloads.get(id).is_some() {
+            Arc::make_mut(&mut self.payloads).remove(id);
+            self.payloads_dirty = true;
+        }
+    }
+
+    /// Removes every vector whose TTL has run out, returning how many.
+    pub fn expire(&mut self) -> Result<usize> {
+        let expired: Vec<ID> = self.expiry.expired(SystemTime::now()).collect();
+        for id in expired.iter() {
+            self.remove_vector(*id)?;
+        }
+        Ok(expired.len())
+    }
+
+    pub fn remove_vector(&mut self, id: ID) -> Result<()> {
+        if !self.backend.vector_exists(id) {
+            self.expiry_dirty |= self.expiry.clear(id);
+            self.clear_payload(id);
+            return Ok(());
+        }
+        let old = self.before_logged_change(id, true)?;
+        self.expiry_dirty |= self.expiry.clear(id);
+        self.clear_payload(id);
+        self.remove_from_bitmaps(id);
+        self.backend.remove_vector(id)?;
+        let logged = self.log_change(|| ChangeEvent::Delete { id });
+        if logged.is_err() {
+            self.restore(id, old)?;
+        }
+        logged
+    }
+
+    /// Starts recording every change to a log in `dir`. A new log begins
+    /// with the bases, a put for every vector already stored and every TTL,
+    /// so a follower replaying it from the start ends up with the same
+    /// store. A change that can't be logged is undone before its error is
+    /// returned, so the store never gets ahead of the log.
+    pub fn open_change_log(&mut self, dir: PathBuf) -> Result<()> {
+        let mut log = ChangeLog::open(dir)?;
+        if log.last_seq() == 0 {
+            log.append(ChangeEvent::BasesChanged {
+                bases: self.bases.to_vec(),
+            })?;
+            for id in self.backend.iter_vector_ids() {
+                let vector = self.backend.get_vector(id)?;
+                log.append(ChangeEvent::Put { id, vector })?;
+            }
+            for (id, at) in self.expiry.iter() {
+                log.append(ChangeEvent::SetExpiry {
+                    id,
+                    at: to_millis(at),
+                })?;
+            }
+        }
+        self.change_log = Some(log);
+        Ok(())
+    }
+
+    pub fn change_log(&self) -> Option<&ChangeLog> {
+        self.change_log.as_ref()
+    }
+
+    /// Subscribes to changes after `seq`; see `ChangeLog::subscribe`.
+    pub fn subscribe(&mut self, seq: u64) -> Result<Receiver<Change>> {
+        match self.change_log.as_mut() {
+            Some(log) => log.subscribe(seq),
+            None => Err(anyhow!("No change log is open")),
+        }
+    }
+
+    fn log_change(&mut self, event: impl FnOnce() -> ChangeEvent) -> Result<()> {
+        if let Some(log) = self.change_log.as_mut() {
+            log.append(event())?;
         }
         Ok(())
     }
 
+    // What `restore` needs to undo a change to `id` that can't be logged.
+    // Nothing is kept unless a log is open.
+    fn before_logged_change(&self, id: ID, exists: bool) -> Result<Option<Undo>> {
+        if self.change_log.is_none() {
+            return Ok(None);
+        }
+        let vector = match exists {
+            true => Some(self.backend.get_vector(id)?),
+            false => None,
+        };
+        let payload = self.payloads.get(id).cloned();
+        Ok(Some((vector, self.expiry.get(id), payload)))
+    }
+
+    // Puts `id` back the way it was, so the store still matches the log.
+    fn restore(&mut self, id: ID, old: Option<Undo>) -> Result<()> {
+        let Some((vector, expiry, payload)) = old else {
+            return Ok(());
+        };
+        if self.backend.vector_exists(id) {
+            self.remove_from_bitmaps(id);
+            self.backend.remove_vector(id)?;
+        }
+        if let Some(v) = vector {
+            self.backend.put_vector(id, &v)?;
+            self.add_to_bitmaps(id, &v)?;
+        }
+        if let Some(at) = expiry {
+            self.expiry.set(id, at);
+        }
+        if let Some(p) = payload {
+            Arc::make_mut(&mut self.payloads).set(id, p)?;
+        }
+        Ok(())
+    }
+
+    /// Persists the bases and bitmaps through the backend.
+    pub fn sync(&mut self) -> Result<()> {
+        if self.bases_dirty {
+            self.backend.save_bases(&self.bases)?;
+            self.bases_dirty = false;
+        }
+        for (bi, faces) in self.bitmaps.iter().enumerate() {
+            for (index, bm) in faces.iter() {
+                self.backend.save_bitmap(bi, *index, bm.as_ref())?;
+            }
+        }
+        if self.expiry_dirty {
+            self.backend
+                .save_blob(EXPIRY_BLOB, &self.expiry.to_bytes()?)?;
+            self.expiry_dirty = false;
+        }
+        if self.payloads_dirty {
+            self.backend
+                .save_blob(PAYLOADS_BLOB, &self.payloads.to_bytes()?)?;
+            self.payloads_dirty = false;
+        }
+        if let Some(log) = &self.change_log {
+            log.sync()?;
+        }
+        self.backend.sync()
+    }
+
+    pub fn close(mut self) -> Result<()> {
+        self.sync()?;
+        self.backend.close()
+    }
+
     pub fn find_nearest(
         &self,
         target: &Vector,
@@ -80,48 +438,198 @@ impl<E: VectorBackend, B: Bitmap> VectorStore<E, B> {
         search_k: usize,
         spill: usize,
     ) -> Result<ResultSet> {
-        let sp = if spill >= self.dimensions {
-            self.dimensions - 1
-        } else {
-            spill
-        };
-        self.find_nearest_internal(target, k, search_k, sp)
+        let bs = self.candidates(target, self.clamp_spill(spill));
+        let opts = SearchOptions::new(k, search_k, spill);
+        self.find_nearest_internal(&bs, k, &opts, self.backend.scorer(target)?)
+    }
+
+    pub fn find_nearest_with(&self, target: &Vector, opts: &SearchOptions) -> Result<ResultSet> {
+        let bs = self.candidates(target, self.clamp_spill(opts.spill));
+        self.search_vector(target, &bs, opts)
+    }
+
+    /// Searches only among the IDs in `filter`, such as a bitmap compiled
+    /// from a `PayloadStore` filter.
+    pub fn find_nearest_filtered(
+        &self,
+        target: &Vector,
+        opts: &SearchOptions,
+        filter: &B,
+    ) -> Result<ResultSet> {
+        let spill = self.clamp_spill(opts.spill);
+        let bs = self.candidates_filtered(&[target], spill, Some(filter));
+        self.search_vector(target, &bs, opts)
+    }
+
+    fn search_vector(
+        &self,
+        target: &Vector,
+        bs: &CountingBitmap<B>,
+        opts: &SearchOptions,
+    ) -> Result<ResultSet> {
+        let score = self.backend.scorer(target)?;
+        let rs = self.find_nearest_internal(bs, opts.candidate_pool(), opts, score)?;
+        let rs = self.rescore(rs, opts, self.backend.exact_scorer(target)?)?;
+        self.rerank(rs, opts)
+    }
+
+    /// Searches with several weighted positive and negative examples. Each
+    /// example picks its own faces in every basis and contributes to the
+    /// score.
+    pub fn find_nearest_query(&self, query: &Query, opts: &SearchOptions) -> Result<ResultSet> {
+        if query.is_empty() {
+            return Err(anyhow!("Query has no positive examples"));
+        }
+        let bs = self.candidates_for(&query.vectors(), self.clamp_spill(opts.spill));
+        let score = query.scorer(&self.backend)?;
+        let rs = self.find_nearest_internal(&bs, opts.candidate_pool(), opts, score)?;
+        let rs = self.rescore(rs, opts, query.exact_scorer(&self.backend)?)?;
+        self.rerank(rs, opts)
     }
 
     #[inline(always)]
     fn find_nearest_internal(
         &self,
-        target: &Vector,
+        bs: &CountingBitmap<B>,
         k: usize,
-        search_k: usize,
-        spill: usize,
+        opts: &SearchOptions,
+        score: impl Fn(ID) -> Result<f32>,
     ) -> Result<ResultSet> {
         let mut rs = ResultSet::new(k);
+        // A small store, shard or filter may not have search_k candidates
+        // at all; then every candidate gets scored.
+        let search_k = opts.search_k.min(bs.layer(0).map_or(0, |l| l.count()));
+        if !opts.has_budget() {
+            let elems = bs
+                .top_k(search_k)
+                .ok_or(anyhow!("Didn't find a counting layer?"))?;
+            for id in elems.iter_elems() {
+                rs.add_result(id, score(id)?);
+            }
+            return Ok(rs);
+        }
+        let top = bs
+            .top_level(search_k)
+            .ok_or(anyhow!("Didn't find a counting layer?"))?;
+        // With a budget, score the candidates that matched the most bases
+        // first, so that stopping early keeps the likeliest matches.
+        let mut seen = B::new();
+        for level in (top..bs.depth()).rev() {
+            let mut fresh = bs.layer(level).unwrap().clone();
+            fresh.and_not(&seen);
+            for id in fresh.iter_elems() {
+                if opts.out_of_budget(rs.checked) {
+                    rs.partial = true;
+                    return Ok(rs);
+                }
+                rs.add_result(id, score(id)?);
+            }
+            seen.or(&fresh);
+        }
+        Ok(rs)
+    }
+
+    fn rescore(
+        &self,
+        rs: ResultSet,
+        opts: &SearchOptions,
+        score: impl Fn(ID) -> Result<f32>,
+    ) -> Result<ResultSet> {
+        match opts.rerank_k {
+            Some(n) => rs.rescore(n, opts.pool_size(), score),
+            None => Ok(rs),
+        }
+    }
+
+    fn rerank(&self, rs: ResultSet, opts: &SearchOptions) -> Result<ResultSet> {
+        match opts.mmr_lambda {
+            Some(lambda) => rs.rerank_mmr(&self.backend, opts.k, lambda),
+            None => Ok(rs),
+        }
+    }
+
+    /// Opens a cursor that returns results a page at a time, starting from
+    /// the same candidates `find_nearest` would score for `search_k`. Later
+    /// pages may hold better matches than earlier ones; see
+    /// `BestEffortCursor`.
+    pub fn best_effort_cursor(
+        &self,
+        target: &Vector,
+        search_k: usize,
+        spill: usize,
+    ) -> Result<BestEffortCursor<'_, E, B>> {
+        BestEffortCursor::new(self, target, search_k, self.clamp_spill(spill))
+    }
+
+    /// Finds the best `groups` groups of results, where `key` maps an ID to
+    /// its group (say, the document a chunk came from), with up to
+    /// `per_group` hits in each. The candidate pool widens from `search_k`
+    /// until enough distinct groups turn up, so one large group can't crowd
+    /// out the rest, and past that while wider pages still beat the last
+    /// group kept. `PayloadStore::group_key` groups by a stored field.
+    pub fn find_nearest_grouped<K: PartialEq>(
+        &self,
+        target: &Vector,
+        groups: usize,
+        per_group: usize,
+        search_k: usize,
+        spill: usize,
+        key: impl Fn(ID) -> Option<K>,
+    ) -> Result<Vec<Group<K>>> {
+        let cursor = self.best_effort_cursor(target, search_k, spill)?;
+        grouped::collect_groups(cursor, groups, per_group.max(1), search_k.max(1), key)
+    }
+
+    pub(crate) fn clamp_spill(&self, spill: usize) -> usize {
+        if spill >= self.dimensions {
+            self.dimensions - 1
+        } else {
+            spill
+        }
+    }
+
+    pub(crate) fn candidates(&self, target: &Vector, spill: usize) -> CountingBitmap<B> {
+        self.candidates_for(&[target], spill)
+    }
+
+    /// Counts, per ID, the bases in which any of the targets' faces hold it.
+    pub(crate) fn candidates_for(&self, targets: &[&Vector], spill: usize) -> CountingBitmap<B> {
+        self.candidates_filtered(targets, spill, None)
+    }
+
+    pub(crate) fn candidates_filtered(
+        &self,
+        targets: &[&Vector],
+        spill: usize,
+        filter: Option<&B>,
+    ) -> CountingBitmap<B> {
         let mut bs = CountingBitmap::<B>::new(self.bases.len());
         let mut proj: Vec<f32> = Vec::with_capacity(self.dimensions);
+        let expired = self.expired_now();
         for (i, basis) in self.bases.iter().enumerate() {
             let mut spill_into = B::new();
-            proj.clear();
-            for b in basis {
-                proj.push(dot_product(target, b))
+            for target in targets {
+                proj.clear();
+                for b in basis {
+                    proj.push(dot_product(target, b))
+                }
+                for _s in 0..(spill + 1) {
+                    let face_idx = find_face_idx(&proj);
+                    if let Some(bm) = self.bitmaps[i].get(&face_idx) {
+                        spill_into.or(bm.as_ref());
+                    };
+                    proj[(face_idx.unsigned_abs() - 1) as usize] = 0.0;
+                }
+            }
+            if let Some(f) = filter {
+                spill_into.and(f);
             }
-            for _s in 0..(spill + 1) {
-                let face_idx = find_face_idx(&proj);
-                if let Some(bm) = self.bitmaps[i].get(&face_idx) {
-                    spill_into.or(bm);
-                };
-                proj[(face_idx.unsigned_abs() - 1) as usize] = 0.0;
+            if let Some(e) = &expired {
+                spill_into.and_not(e);
             }
             bs.or(spill_into);
         }
-        let elems = bs
-            .top_k(search_k)
-            .ok_or(anyhow!("Didn't find a counting layer?"))?;
-        for id in elems.iter_elems() {
-            let sim = self.backend.compute_similarity(target, id)?;
-            rs.add_result(id, sim);
-        }
-        Ok(rs)
+        bs
     }
 
     #[allow(unused)]
@@ -133,16 +641,198 @@ impl<E: VectorBackend, B: Bitmap> VectorStore<E, B> {
                 proj.push(dot_product(vec, b));
             }
             let face_idx = find_face_idx(&proj);
-            self.bitmaps[bi].entry(face_idx).or_default().add(id);
+            Arc::make_mut(self.bitmaps[bi].entry(face_idx).or_default()).add(id);
+        }
+        Ok(())
+    }
+
+    fn remove_from_bitmaps(&mut self, id: ID) {
+        for faces in self.bitmaps.iter_mut() {
+            // Only copy the bitmap a snapshot still shares if it changes.
+            for bm in faces.values_mut().filter(|bm| bm.contains(id)) {
+                Arc::make_mut(bm).remove(id);
+            }
+        }
+    }
+
+    /// Copies every vector of `other` into this store, along with its TTL
+    /// and payload, returning how many.
+    ///
+    /// The stores must have the same dimensions and quantizers, trained
+    /// alike, since vectors are copied over without retraining. When an
+    /// ID is already taken here, `remap` picks the ID to store it under
+    /// instead, or `None` to keep this store's vector and skip it. If both
+    /// stores use the same bases, the face bitmaps are combined directly
+    /// rather than reprojecting every vector.
+    ///
+    /// Every target ID is checked before anything is written. If a write
+    /// fails partway, the vectors already copied stay, and stay indexed.
+    pub fn merge<E2: VectorBackend>(
+        &mut self,
+        other: &VectorStore<E2, B>,
+        mut remap: impl FnMut(ID) -> Option<ID>,
+    ) -> Result<usize> {
+        let (mine, theirs) = (self.backend.info(), other.backend.info());
+        if mine.dimensions != theirs.dimensions {
+            return Err(anyhow!(
+                "Can't merge {} dimensions into {}",
+                theirs.dimensions,
+                mine.dimensions
+            ));
+        }
+        if mine.quantization != theirs.quantization {
+            return Err(anyhow!(
+                "Can't merge quantization {} into {}",
+                theirs.quantization,
+                mine.quantization
+            ));
+        }
+        if self.backend.quantizer_state()? != other.backend.quantizer_state()? {
+            return Err(anyhow!(
+                "Can't merge a differently trained {} quantizer",
+                theirs.quantization
+            ));
+        }
+        // Pick and check every target before writing anything.
+        let mut plan = Vec::new();
+        let mut targets = B::new();
+        for id in other.backend.iter_vector_ids() {
+            let target = match self.backend.vector_exists(id) {
+                true => match remap(id) {
+                    Some(target) => target,
+                    None => continue,
+                },
+                false => id,
+            };
+            if target > B::MAX_ID {
+                return Err(anyhow!(
+                    "ID {} is out of range for this bitmap type",
+                    target
+                ));
+            }
+            let taken = target != id && self.backend.vector_exists(target);
+            if taken || targets.contains(target) {
+                return Err(anyhow!("Remapped ID {} is also taken", target));
+            }
+            targets.add(target);
+            plan.push((id, target));
+        }
+        // With the same bases, new IDs keep their faces, and only their
+        // face bitmaps are copied over once they're written.
+        let same_bases = self.bases == other.bases;
+        let mut copied = B::new();
+        let result = self.merge_planned(other, &plan, same_bases, &mut copied);
+        if same_bases {
+            for (faces, theirs) in self.bitmaps.iter_mut().zip(other.bitmaps.iter()) {
+                for (face, bm) in theirs.iter() {
+                    let mut bm = bm.as_ref().clone();
+                    bm.and(&copied);
+                    if !bm.is_empty() {
+                        Arc::make_mut(faces.entry(*face).or_default()).or(&bm);
+                    }
+                }
+            }
+        }
+        result.map(|_| plan.len())
+    }
+
+    // Writes a checked merge plan. IDs written without reprojecting are
+    // added to `copied` as they go, so they're indexed even if a later
+    // write fails.
+    fn merge_planned<E2: VectorBackend>(
+        &mut self,
+        other: &VectorStore<E2, B>,
+        plan: &[(ID, ID)],
+        same_bases: bool,
+        copied: &mut B,
+    ) -> Result<()> {
+        for &(id, target) in plan {
+            let vector = other.backend.get_vector(id)?;
+            if same_bases && target == id && !self.backend.vector_exists(id) {
+                self.backend.put_vector(id, &vector)?;
+                let logged = self.log_change(|| ChangeEvent::Put {
+                    id,
+                    vector: vector.clone(),
+                });
+                if logged.is_err() {
+                    self.backend.remove_vector(id)?;
+                    return logged;
+                }
+                copied.add(id);
+            } else {
+                self.add_vector(target, &vector)?;
+            }
+            if let Some(at) = other.expiry.get(id) {
+                self.set_expiry(target, at)?;
+            }
+            if let Some(p) = other.payloads.get(id) {
+                self.set_payload(target, p.clone())?;
+            }
         }
         Ok(())
     }
 
+    /// Takes a point-in-time view of the store. The snapshot shares the
+    /// bitmaps and vectors with the store, which copies whatever it changes
+    /// afterwards, so it never sees a half-applied write. TTLs are judged
+    /// as of when it was taken.
+    pub fn snapshot(&self) -> Result<Snapshot<E::Snapshot, B>>
+    where
+        E: SnapshotBackend,
+    {
+        Ok(Snapshot::new(VectorStore {
+            backend: self.backend.snapshot()?,
+            dimensions: self.dimensions,
+            bases: self.bases.clone(),
+            bitmaps: self.bitmaps.clone(),
+            bases_dirty: false,
+            expiry: self.expiry.clone(),
+            expiry_dirty: false,
+            payloads: self.payloads.clone(),
+            payloads_dirty: false,
+            change_log: None,
+            frozen_at: Some(self.frozen_at.unwrap_or_else(SystemTime::now)),
+        }))
+    }
+
     pub fn full_table_scan(&self, vec: &Vector, k: usize) -> Result<ResultSet> {
-        self.backend.find_nearest(vec, k)
+        let Some(expired) = self.expired_now() else {
+            return self.backend.find_nearest(vec, k);
+        };
+        // Score the same way either way, with room to drop the expired.
+        let all = self.backend.find_nearest(vec, k + expired.count())?;
+        let mut set = ResultSet::new(k);
+        for r in all.iter_results().filter(|r| !expired.contains(r.id)) {
+            set.add_result(r.id, r.similarity);
+        }
+        set.checked = all.checked;
+        Ok(set)
+    }
+
+    // The IDs past their TTL that `expire` hasn't removed yet, if any.
+    fn expired_now(&self) -> Option<B> {
+        if self.expiry.is_empty() {
+            return None;
+        }
+        let now = self.frozen_at.unwrap_or_else(SystemTime::now);
+        let expired: B = self.expiry.expired_bitmap(now);
+        (!expired.is_empty()).then_some(expired)
     }
 }
 
+fn check_bases(bases: &[Basis], n_basis: usize, dimensions: usize) -> Result<()> {
+    let square = bases.iter().all(|b| b.len() == dimensions)
+        && bases.iter().flatten().all(|v| v.len() == dimensions);
+    if bases.len() != n_basis || !square {
+        return Err(anyhow!(
+            "Expected {} bases of {} dimensions",
+            n_basis,
+            dimensions
+        ));
+    }
+    Ok(())
+}
+
 fn make_basis(n_basis: usize, dimensions: usize) -> Result<Vec<Basis>> {
     let mut bases = Vec::<Basis>::with_capacity(n_basis);
     for _n in 0..n_basis {
@@ -167,14 +857,14 @@ fn print_basis(basis: &Basis) {
 }
 
 fn orthonormalize(mut basis: Basis, rounds: usize) -> Basis {
-    let dim = basis[0].len();
     for _ in 0..rounds {
         for i in 0..basis.len() {
             normalize(&mut basis[i]);
             for j in i + 1..basis.len() {
                 let dot = dot_product(&basis[i], &basis[j]);
-                for k in 0..dim {
-                    basis[j][k] -= dot * basis[i][k];
+                let (head, tail) = basis.split_at_mut(j);
+                for (x, b) in tail[0].iter_mut().zip(head[i].iter()) {
+                    *x -= dot * b;
                 }
                 normalize(&mut basis[j]);
             }
@@ -183,24 +873,24 @@ fn orthonormalize(mut basis: Basis, rounds: usize) -> Basis {
     basis
 }
 
-fn load_all_bitmaps<B: Bitmap>(be: &mut impl VectorBackend) -> Result<Vec<HashMap<i32, B>>> {
+fn load_all_bitmaps<B: Bitmap>(be: &mut impl VectorBackend) -> Result<Vec<HashMap<i32, Arc<B>>>> {
     let info = be.info();
     let mut out = Vec::with_capacity(info.n_basis);
     for i in 0..info.n_basis {
-        let mut hm = HashMap::<i32, B>::new();
-        for x in 0..info.dimensions {
+        let mut hm = HashMap::<i32, Arc<B>>::new();
+        for x in 1..=info.dimensions {
             let index = x as i32;
             let bit = be.load_bitmap::<B>(i, index)?;
             if let Some(bitmap) = bit {
-                hm.insert(index, bitmap);
+                hm.insert(index, Arc::new(bitmap));
             } else {
-                hm.insert(index, B::default());
+                hm.insert(index, Arc::default());
             }
             let bit = be.load_bitmap::<B>(i, -index)?;
             if let Some(bitmap) = bit {
-                hm.insert(-index, bitmap);
+                hm.insert(-index, Arc::new(bitmap));
             } else {
-                hm.insert(-index, B::default());
+                hm.insert(-index, Arc::default());
             }
         }
         out.push(hm)
@@ -249,6 +939,123 @@ mod test {
         assert_eq!(basis_set[0].len(), 2);
     }
 
+    #[test]
+    fn test_rejects_out_of_range_ids() {
+        let mem = MemoryBackend::new(2, 1).unwrap();
+        let mut store = VectorStore::new_croaring_bitmap(mem).unwrap();
+        let v = vec![1.0, 0.0];
+        store.add_vector(1, &v).unwrap();
+        assert!(store.add_vector(u32::MAX as ID + 1, &v).is_err());
+    }
+
+    #[test]
+    fn test_remove_vector() {
+        let mem = MemoryBackend::new(2, 2).unwrap();
+        let mut store = VectorStore::new(mem).unwrap();
+        store.add_vector_iter(vecs().enumerate_ids()).unwrap();
+        store.remove_vector(1).unwrap();
+        assert!(!store.backend().vector_exists(1));
+        for faces in store.bitmaps.iter() {
+            assert!(faces.values().all(|bm| !bm.contains(1)));
+            assert_eq!(faces.values().map(|bm| bm.count()).sum::<usize>(), 3);
+        }
+        // Re-adding moves the vector rather than duplicating it.
+        store.add_vector(0, &vec![0.0, -1.0]).unwrap();
+        for faces in store.bitmaps.iter() {
+            assert_eq!(faces.values().map(|bm| bm.count()).sum::<usize>(), 3);
+        }
+        // A failed overwrite leaves the old vector indexed.
+        assert!(store.add_vector(2, &vec![1.0, 0.0, 0.0]).is_err());
+        for faces in store.bitmaps.iter() {
+            assert!(faces.values().any(|bm| bm.contains(2)));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_unlogged_changes_are_undone() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut store = VectorStore::new(MemoryBackend::new(2, 2)?)?;
+        store.add_vector_iter(vecs().enumerate_ids())?;
+        store.add_vector_with_ttl(3, &vecs()[3], Duration::from_secs(60))?;
+        store.open_change_log(dir.path().into())?;
+        let faces =
+            |s: &VectorStore<MemoryBackend, crate::CRoaringBitmap>| -> Vec<Vec<(i32, Vec<ID>)>> {
+                s.bitmaps
+                    .iter()
+                    .map(|f| {
+                        let mut f: Vec<_> = f
+                            .iter()
+                            .map(|(k, bm)| (*k, bm.iter_elems().collect()))
+                            .collect();
+                        f.sort();
+                        f
+                    })
+                    .collect()
+            };
+        let (bases, before, expiry) = (store.bases.clone(), faces(&store), store.expires_at(3));
+        let seq = store.change_log().unwrap().last_seq();
+        store.change_log.as_mut().unwrap().fill_disk()?;
+
+        assert!(store.add_vector(0, &vec![0.0, -1.0]).is_err());
+        assert!(store.add_vector(3, &vec![0.0, -1.0]).is_err());
+        assert!(store.add_vector(9, &vec![0.0, -1.0]).is_err());
+        assert!(store.remove_vector(1).is_err());
+        assert!(store.set_expiry(2, SystemTime::now()).is_err());
+        assert!(store.set_bases(make_basis(2, 2)?).is_err());
+        assert_eq!(store.backend().get_vector(0)?, vecs()[0]);
+        assert!(store.backend().vector_exists(1));
+        assert!(!store.backend().vector_exists(9));
+        assert_eq!(store.expires_at(3), expiry);
+        assert_eq!(store.expires_at(2), None);
+        assert_eq!(store.bases, bases);
+        assert_eq!(faces(&store), before);
+        assert_eq!(store.change_log().unwrap().last_seq(), seq);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_copies_only_written_faces() -> Result<()> {
+        let data = crate::create_vector_set(2, 40);
+        let mut a = VectorStore::new(MemoryBackend::new(2, 2)?)?;
+        a.add_vector_iter(data.enumerate_ids().take(20))?;
+        let mut b =
+            VectorStore::new_vector_store_with_bases(MemoryBackend::new(2, 2)?, a.bases.to_vec())?;
+        // b's copies of 10..20 differ from a's, and are skipped.
+        for id in 10..40 {
+            b.add_vector(id, &data[39 - id as usize])?;
+        }
+        assert_eq!(a.merge(&b, |_| None)?, 20);
+        for faces in a.bitmaps.iter() {
+            for id in 0..40 {
+                let n = faces.values().filter(|bm| bm.contains(id)).count();
+                assert_eq!(n, 1, "ID {} is on {} faces", id, n);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_failed_merge_stays_indexed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let data = crate::create_vector_set(2, 20);
+        let mut a = VectorStore::new(MemoryBackend::new(2, 2)?)?;
+        a.open_change_log(dir.path().into())?;
+        let mut b =
+            VectorStore::new_vector_store_with_bases(MemoryBackend::new(2, 2)?, a.bases.to_vec())?;
+        b.add_vector_iter(data.enumerate_ids())?;
+        a.change_log.as_mut().unwrap().fill_disk()?;
+        assert!(a.merge(&b, Some).is_err());
+        for id in a.backend().iter_vector_ids() {
+            assert!(a
+                .bitmaps
+                .iter()
+                .all(|f| f.values().any(|bm| Bitmap::contains(bm.as_ref(), id))));
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_make_bitmaps() {
         //let mem = MemoryBackend::new(2, 2);
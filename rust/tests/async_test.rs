@@ -0,0 +1,144 @@
+#![cfg(feature = "async")]
+use anyhow::Result;
+use bbqvec::{self, backend::VectorBackend, AsyncVectorStore, IndexIDIterator};
+use std::time::Instant;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn async_search() -> Result<()> {
+    let vecs = bbqvec::create_vector_set(20, 2000);
+    let mem = bbqvec::MemoryBackend::new(20, 10)?;
+    let mut store = bbqvec::VectorStore::new(mem)?;
+    store.add_vector_iter(vecs.enumerate_ids())?;
+    let target = bbqvec::create_random_vector(20);
+    let expected = store.find_nearest(&target, 10, 200, 2)?;
+
+    let handle = AsyncVectorStore::new(store);
+    let mut tasks = Vec::new();
+    for _ in 0..4 {
+        let h = handle.clone();
+        let t = target.clone();
+        tasks.push(tokio::spawn(
+            async move { h.find_nearest(t, 10, 200, 2).await },
+        ));
+    }
+    for t in tasks {
+        let rs = t.await??;
+        assert_eq!(rs.compute_recall(&expected, 10), 1.0);
+    }
+
+    handle.add_vector(5000, target.clone()).await?;
+    let rs = handle.full_table_scan(target.clone(), 2000).await?;
+    assert_eq!(rs.len(), 2000);
+    handle.remove_vector(5000).await?;
+    let rs = handle.full_table_scan(target, 3000).await?;
+    assert_eq!(rs.len(), 2000);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn dropped_search_is_cancelled() -> Result<()> {
+    let vecs = bbqvec::create_vector_set(20, 50_000);
+    let mem = bbqvec::MemoryBackend::new(20, 4)?;
+    let mut store = bbqvec::VectorStore::new(mem)?;
+    store.add_vector_iter(vecs.enumerate_ids())?;
+    let target = bbqvec::create_random_vector(20);
+    // Score most of the store, and time how long that takes uninterrupted.
+    let opts = bbqvec::SearchOptions::new(10, 50_000, 19);
+    let start = Instant::now();
+    let full = store.find_nearest_with(
+        &target,
+        &opts
+            .clone()
+            .with_cancellation(bbqvec::CancellationToken::new()),
+    )?;
+    let baseline = start.elapsed();
+    let handle = AsyncVectorStore::new(store);
+
+    // Cancelling the token stops a search that's underway.
+    let cancel = bbqvec::CancellationToken::new();
+    let search = tokio::spawn({
+        let (h, t) = (handle.clone(), target.clone());
+        let opts = opts.clone().with_cancellation(cancel.clone());
+        async move { h.find_nearest_with(t, opts).await }
+    });
+    tokio::time::sleep(baseline / 10).await;
+    cancel.cancel();
+    let rs = search.await??;
+    assert!(rs.partial);
+    assert!(rs.checked < full.checked);
+
+    // So does dropping the future, and the store is free again long before
+    // the search would have finished.
+    let _ = tokio::time::timeout(
+        baseline / 10,
+        handle.find_nearest(target.clone(), 10, 50_000, 19),
+    )
+    .await;
+    let dropped = Instant::now();
+    handle.sync().await?;
+    assert!(dropped.elapsed() < baseline / 2);
+
+    let rs = handle.find_nearest(target, 10, 50_000, 19).await?;
+    assert!(!rs.partial);
+    assert_eq!(rs.compute_recall(&full, 10), 1.0);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn async_search_with_budget() -> Result<()> {
+    let vecs = bbqvec::create_vector_set(20, 1000);
+    let mem = bbqvec::MemoryBackend::new(20, 4)?;
+    let mut store = bbqvec::VectorStore::new(mem)?;
+    store.add_vector_iter(vecs.enumerate_ids())?;
+    let handle = AsyncVectorStore::new(store);
+    let target = bbqvec::create_random_vector(20);
+    let opts = bbqvec::SearchOptions::new(10, 50, 3);
+    let rs = handle
+        .find_nearest_with(target.clone(), opts.clone().with_max_candidates(5))
+        .await?;
+    assert!(rs.partial);
+    assert_eq!(rs.len(), 5);
+    let rs = handle.find_nearest_with(target, opts).await?;
+    assert!(!rs.partial);
+    assert_eq!(rs.len(), 10);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn background_expiry() -> Result<()> {
+    let vecs = bbqvec::create_vector_set(20, 100);
+    let mem = bbqvec::MemoryBackend::new(20, 4)?;
+    let mut store = bbqvec::VectorStore::new(mem)?;
+    store.add_vector_iter(vecs.enumerate_ids())?;
+    store.add_vector_with_ttl(3, &vecs[3], std::time::Duration::from_millis(10))?;
+    let handle = AsyncVectorStore::new(store);
+    let task = handle.spawn_expiry(std::time::Duration::from_millis(20));
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    task.abort();
+    let _ = task.await;
+    let store = handle.try_into_inner()?;
+    assert!(!store.backend().vector_exists(3));
+    assert_eq!(store.backend().info().vector_count, 99);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn snapshot_while_writing() -> Result<()> {
+    let vecs = bbqvec::create_vector_set(20, 1000);
+    let mem = bbqvec::MemoryBackend::new(20, 4)?;
+    let mut store = bbqvec::VectorStore::new(mem)?;
+    store.add_vector_iter(vecs.enumerate_ids().take(500))?;
+    let handle = AsyncVectorStore::new(store);
+    let snap = handle.snapshot().await?;
+    let rest: Vec<_> = vecs
+        .into_iter()
+        .enumerate()
+        .skip(500)
+        .map(|(i, v)| (i as u64, v))
+        .collect();
+    handle.add_vectors(rest).await?;
+    assert_eq!(snap.backend().info().vector_count, 500);
+    let snap = handle.snapshot().await?;
+    assert_eq!(snap.backend().info().vector_count, 1000);
+    Ok(())
+}
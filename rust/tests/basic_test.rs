@@ -45,3 +45,25 @@ fn built_big_index() -> Result<()> {
     store.add_vector_iter(vecs.enumerate_ids())?;
     Ok(())
 }
+
+#[test]
+fn sparse_ids() -> Result<()> {
+    let vecs = bbqvec::create_vector_set(20, 4);
+    let ids: [bbqvec::ID; 4] = [
+        1_000_000_000,
+        u32::MAX as u64,
+        u32::MAX as u64 + 1,
+        u64::MAX,
+    ];
+    let mem = bbqvec::MemoryBackend::new(20, 4)?;
+    let mut store = bbqvec::VectorStore::new_croaring_treemap(mem)?;
+    store.add_vector_iter(ids.into_iter().zip(vecs.iter()))?;
+    assert_eq!(store.backend().info().vector_count, 4);
+    for (id, v) in ids.iter().zip(vecs.iter()) {
+        let rs = store.find_nearest(v, 4, 4, 19)?;
+        assert!(rs.iter_results().any(|r| r.id == *id));
+    }
+    store.remove_vector(u32::MAX as u64 + 1)?;
+    assert_eq!(store.backend().info().vector_count, 3);
+    Ok(())
+}
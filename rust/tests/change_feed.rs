@@ -0,0 +1,42 @@
+use anyhow::Result;
+use bbqvec::{self, ChangeEvent, IndexIDIterator};
+
+#[test]
+fn change_feed() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let vecs = bbqvec::create_vector_set(20, 100);
+    let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(dir.path().into(), 20, 4)?;
+    let mut store = bbqvec::VectorStore::new(be)?;
+    store.add_vector_iter(vecs.enumerate_ids().take(10))?;
+    let log_dir = store.backend().path().join("changes");
+    store.open_change_log(log_dir.clone())?;
+    // The bases, then the ten vectors that were already there.
+    assert_eq!(store.change_log().unwrap().last_seq(), 11);
+
+    let rx = store.subscribe(11)?;
+    store.add_vector_iter(vecs.enumerate_ids().skip(10))?;
+    store.remove_vector(4)?;
+    store.remove_vector(4)?;
+    store.sync()?;
+    let changes: Vec<_> = rx.try_iter().collect();
+    assert_eq!(changes.len(), 91);
+    assert_eq!(changes[0].seq, 12);
+    assert!(matches!(changes[0].event, ChangeEvent::Put { id: 10, .. }));
+    assert_eq!(changes[90].event, ChangeEvent::Delete { id: 4 });
+    assert!(changes.windows(2).all(|w| w[1].seq == w[0].seq + 1));
+    store.close()?;
+
+    // Reopening picks up where the log left off.
+    let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(dir.path().into(), 20, 4)?;
+    let mut store = bbqvec::VectorStore::new(be)?;
+    store.open_change_log(log_dir)?;
+    let log = store.change_log().unwrap();
+    assert_eq!(log.last_seq(), 102);
+    let all = log.changes_since(0)?;
+    assert!(matches!(all[0].event, ChangeEvent::BasesChanged { .. }));
+    let rx = store.subscribe(100)?;
+    store.remove_vector(5)?;
+    let seqs: Vec<_> = rx.try_iter().map(|c| c.seq).collect();
+    assert_eq!(seqs, vec![101, 102, 103]);
+    Ok(())
+}
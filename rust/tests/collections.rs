@@ -0,0 +1,73 @@
+use anyhow::Result;
+use bbqvec::{self, CollectionConfig, CollectionManager, IndexIDIterator};
+
+#[test]
+fn create_list_drop() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let a = bbqvec::create_vector_set(16, 200);
+    let b = bbqvec::create_vector_set(32, 200);
+    {
+        let mut mgr =
+            CollectionManager::<bbqvec::CRoaringTreemap>::open(dir.path().into(), 1 << 30)?;
+        mgr.create("alpha", CollectionConfig::new(16, 4, "none"))?
+            .add_vector_iter(a.enumerate_ids())?;
+        mgr.create("beta", CollectionConfig::new(32, 6, "bf16"))?
+            .add_vector_iter(b.enumerate_ids())?;
+        assert!(mgr
+            .create("alpha", CollectionConfig::new(16, 4, "none"))
+            .is_err());
+        assert!(mgr
+            .create("../escape", CollectionConfig::new(16, 4, "none"))
+            .is_err());
+        assert!(mgr
+            .create("gamma", CollectionConfig::new(16, 4, "nope"))
+            .is_err());
+        assert!(mgr
+            .create("gamma", CollectionConfig::new(16, 4, "pq"))
+            .is_err());
+        for (name, q) in [("delta", "int8"), ("epsilon", "binary")] {
+            mgr.create(name, CollectionConfig::new(16, 4, q))?
+                .add_vector_iter(a.enumerate_ids())?;
+        }
+        mgr.close()?;
+    }
+    let mut mgr = CollectionManager::<bbqvec::CRoaringTreemap>::open(dir.path().into(), 1 << 30)?;
+    assert_eq!(
+        mgr.list().collect::<Vec<_>>(),
+        vec!["alpha", "beta", "delta", "epsilon"]
+    );
+    assert_eq!(mgr.get("delta")?.vector_count(), 200);
+    let rs = mgr.get("epsilon")?.find_nearest(&a[5], 5, 50, 2)?;
+    assert_eq!(rs.len(), 5);
+    assert_eq!(mgr.config("beta").unwrap().quantization, "bf16");
+    assert_eq!(mgr.get("alpha")?.vector_count(), 200);
+    let rs = mgr.get("beta")?.find_nearest(&b[5], 5, 50, 2)?;
+    assert_eq!(rs.len(), 5);
+
+    mgr.drop_collection("alpha")?;
+    assert_eq!(
+        mgr.list().collect::<Vec<_>>(),
+        vec!["beta", "delta", "epsilon"]
+    );
+    assert!(mgr.get("alpha").is_err());
+    assert!(!dir.path().join("alpha").exists());
+    Ok(())
+}
+
+#[test]
+fn memory_budget_evicts() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let data = bbqvec::create_vector_set(16, 500);
+    let mut mgr = CollectionManager::<bbqvec::CRoaringTreemap>::open(dir.path().into(), 1)?;
+    for name in ["one", "two", "three"] {
+        mgr.create(name, CollectionConfig::new(16, 4, "none"))?
+            .add_vector_iter(data.enumerate_ids())?;
+    }
+    // Nothing fits in a one-byte budget, so only the collection in use stays open.
+    assert_eq!(mgr.open_count(), 1);
+    for name in ["one", "two", "three"] {
+        assert_eq!(mgr.get(name)?.vector_count(), 500);
+        assert_eq!(mgr.open_count(), 1);
+    }
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use anyhow::Result;
+use bbqvec::{self, backend::VectorBackend, IndexIDIterator, Quantization};
+
+#[test]
+fn disk_backend_reopens() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let vecs = bbqvec::create_vector_set(20, 500);
+    let target = bbqvec::create_random_vector(20);
+    let before = {
+        let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(dir.path().into(), 20, 4)?;
+        let mut store = bbqvec::VectorStore::new(be)?;
+        store.add_vector_iter(vecs.enumerate_ids())?;
+        store.remove_vector(3)?;
+        let rs = store.find_nearest(&target, 10, 100, 2)?;
+        store.close()?;
+        rs
+    };
+    let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(dir.path().into(), 20, 4)?;
+    assert!(be.info().has_index_data);
+    assert_eq!(be.info().vector_count, 499);
+    assert!(!be.vector_exists(3));
+    let store = bbqvec::VectorStore::new(be)?;
+    let after = store.find_nearest(&target, 10, 100, 2)?;
+    let ids = |rs: &bbqvec::ResultSet| rs.iter_results().map(|r| r.id).collect::<Vec<_>>();
+    assert_eq!(ids(&before), ids(&after));
+    Ok(())
+}
+
+#[test]
+fn keyed_store_persists_mapping() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let vecs = bbqvec::create_vector_set(20, 100);
+    {
+        let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(dir.path().into(), 20, 4)?;
+        let mut store = bbqvec::KeyedVectorStore::new(bbqvec::VectorStore::new(be)?)?;
+        store.add_vector_iter(
+            vecs.iter()
+                .enumerate()
+                .map(|(i, v)| (format!("doc-{}", i), v)),
+        )?;
+        assert!(store.remove_vector(&"doc-7".to_string())?);
+        assert!(!store.remove_vector(&"doc-7".to_string())?);
+        store.close()?;
+    }
+    let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(dir.path().into(), 20, 4)?;
+    let mut store = bbqvec::KeyedVectorStore::<String, _, _>::new(bbqvec::VectorStore::new(be)?)?;
+    assert_eq!(store.key_map().len(), 99);
+    let rs = store.full_table_scan(&vecs[12], 100)?;
+    assert_eq!(rs.len(), 99);
+    assert!(rs.iter().any(|r| r.key == "doc-12"));
+    assert!(rs.iter().all(|r| r.key != "doc-7"));
+
+    // The freed ID is reused for the next new key.
+    let id = store.add_vector("fresh".to_string(), &vecs[7])?;
+    assert_eq!(id, 7);
+    Ok(())
+}
+
+#[test]
+fn ttl_hides_and_expires() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let vecs = bbqvec::create_vector_set(20, 200);
+    let past = std::time::SystemTime::now() - std::time::Duration::from_secs(1);
+    {
+        let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(dir.path().into(), 20, 4)?;
+        let mut store = bbqvec::VectorStore::new(be)?;
+        store.add_vector_iter(vecs.enumerate_ids())?;
+        let before = store.full_table_scan(&vecs[7], 10)?;
+        store.add_vector_with_expiry(7, &vecs[7], past)?;
+        store.add_vector_with_ttl(8, &vecs[8], std::time::Duration::from_secs(3600))?;
+        // Hidden right away, even though it's still stored.
+        let rs = store.find_nearest(&vecs[7], 1, 1, 0)?;
+        assert_ne!(rs.iter_results().next().unwrap().id, 7);
+        let rs = store.full_table_scan(&vecs[7], 200)?;
+        assert_eq!(rs.len(), 199);
+        assert!(store.backend().vector_exists(7));
+        // The rest score the same as before 7 expired.
+        let after = store.full_table_scan(&vecs[7], 9)?;
+        let kept: Vec<_> = before.iter_results().filter(|r| r.id != 7).collect();
+        assert_eq!(after.iter_results().collect::<Vec<_>>(), kept);
+        store.close()?;
+    }
+    let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(dir.path().into(), 20, 4)?;
+    let mut store = bbqvec::VectorStore::new(be)?;
+    assert!(store.expires_at(8).is_some());
+    assert_eq!(store.full_table_scan(&vecs[7], 200)?.len(), 199);
+    assert_eq!(store.expire()?, 1);
+    assert!(!store.backend().vector_exists(7));
+    assert!(store.expires_at(7).is_none());
+    assert_eq!(store.expire()?, 0);
+    // Re-adding without a TTL makes the vector permanent.
+    store.add_vector(8, &vecs[8])?;
+    assert!(store.expires_at(8).is_none());
+    Ok(())
+}
+
+#[test]
+fn int8_calibration_persists() -> Result<()> {
+    type Int8Disk = bbqvec::DiskBackend<bbqvec::Int8Quantization>;
+    let dir = tempfile::tempdir()?;
+    let vecs = bbqvec::create_vector_set(20, 500);
+    let mut quantizer = bbqvec::Int8Quantization::new(bbqvec::Calibration::Percentile(0.5));
+    quantizer.train(&vecs)?;
+    let target = bbqvec::create_random_vector(20);
+    let before = {
+        let be = Int8Disk::open_with_quantizer(dir.path().into(), 20, 4, quantizer.clone())?;
+        let mut store = bbqvec::VectorStore::new(be)?;
+        store.add_vector_iter(vecs.enumerate_ids())?;
+        let rs = store.full_table_scan(&target, 10)?;
+        store.close()?;
+        rs
+    };
+    // Plain open picks up the stored calibration.
+    let be = Int8Disk::open(dir.path().into(), 20, 4)?;
+    assert_eq!(be.quantizer(), &quantizer);
+    let after = be.find_nearest(&target, 10)?;
+    let ids = |rs: &bbqvec::ResultSet| rs.iter_results().map(|r| r.id).collect::<Vec<_>>();
+    assert_eq!(ids(&before), ids(&after));
+    drop(be);
+
+    let mut other = bbqvec::Int8Quantization::new(bbqvec::Calibration::Global);
+    other.train(&vecs[..100])?;
+    assert!(Int8Disk::open_with_quantizer(dir.path().into(), 20, 4, other).is_err());
+
+    // Quantized scores stay close to exact ones.
+    let mut exact = bbqvec::MemoryBackend::new(20, 4)?;
+    for (id, v) in vecs.enumerate_ids() {
+        exact.put_vector(id, v)?;
+    }
+    let truth = exact.find_nearest(&target, 10)?;
+    assert!(after.compute_recall(&truth, 10) >= 0.7);
+    Ok(())
+}
+
+#[test]
+fn pq_codebooks_persist() -> Result<()> {
+    type PqDisk = bbqvec::DiskBackend<bbqvec::ProductQuantization<8>>;
+    let dir = tempfile::tempdir()?;
+    let vecs = bbqvec::create_vector_set(32, 2000);
+    let mut quantizer = bbqvec::ProductQuantization::<8>::new(8);
+    quantizer.train(&vecs[..500])?;
+    let target = bbqvec::create_random_vector(32);
+    let before = {
+        let be = PqDisk::open_with_quantizer(dir.path().into(), 32, 6, quantizer)?;
+        let mut store = bbqvec::VectorStore::new(be)?;
+        store.add_vector_iter(vecs.enumerate_ids())?;
+        let rs = store.find_nearest(&target, 10, 200, 2)?;
+        store.close()?;
+        rs
+    };
+    // A byte per subspace, after a bit per slot.
+    let vec_file = std::fs::metadata(dir.path().join("0.vec"))?;
+    assert_eq!(vec_file.len(), 8 * 200_000 + 25_024);
+
+    let be = PqDisk::open(dir.path().into(), 32, 6)?;
+    let store = bbqvec::VectorStore::new(be)?;
+    let after = store.find_nearest(&target, 10, 200, 2)?;
+    let ids = |rs: &bbqvec::ResultSet| rs.iter_results().map(|r| r.id).collect::<Vec<_>>();
+    assert_eq!(ids(&before), ids(&after));
+    Ok(())
+}
+
+#[test]
+fn payloads_go_with_their_vectors() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let vecs = bbqvec::create_vector_set(20, 10);
+    let past = std::time::SystemTime::now() - std::time::Duration::from_secs(1);
+    let tagged = |tag: &str| bbqvec::Payload::from([("tag".to_string(), tag.into())]);
+    let red = bbqvec::Filter::eq("tag", "red");
+    {
+        let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(dir.path().into(), 20, 4)?;
+        let mut store = bbqvec::VectorStore::new(be)?;
+        store.add_vector_iter(vecs.enumerate_ids())?;
+        store.add_vector_with_expiry(9, &vecs[9], past)?;
+        for id in [1, 2, 9] {
+            store.set_payload(id, tagged("red"))?;
+        }
+        assert!(store.set_payload(100, tagged("red")).is_err());
+        store.remove_vector(1)?;
+        assert!(store.payloads().get(1).is_none());
+        store.close()?;
+    }
+    let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(dir.path().into(), 20, 4)?;
+    let mut store = bbqvec::VectorStore::new(be)?;
+    let ids = |s: &bbqvec::VectorStore<_, bbqvec::CRoaringBitmap>| -> Result<Vec<u32>> {
+        Ok(s.payloads().compile(&red)?.iter().collect())
+    };
+    assert_eq!(ids(&store)?, vec![2, 9]);
+    assert_eq!(store.expire()?, 1);
+    assert_eq!(ids(&store)?, vec![2]);
+    Ok(())
+}
+
+#[test]
+fn binary_vectors_keep_their_dimensions() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let vecs = bbqvec::create_vector_set(20, 50);
+    for reopen in [false, true] {
+        let be = bbqvec::DiskBackend::<bbqvec::BinaryQuantization>::open(dir.path().into(), 20, 4)?;
+        let mut store = bbqvec::VectorStore::new_croaring_bitmap(be)?;
+        if !reopen {
+            store.add_vector_iter(vecs.enumerate_ids())?;
+        }
+        // 20 sign bits take three bytes, four of them padding.
+        let raised = store.backend().get_vector(7)?;
+        assert_eq!(raised.len(), 20);
+        assert!(raised
+            .iter()
+            .zip(&vecs[7])
+            .all(|(r, v)| (*r >= 0.0) == (*v >= 0.0)));
+        store.close()?;
+    }
+    Ok(())
+}
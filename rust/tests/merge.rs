@@ -0,0 +1,112 @@
+use anyhow::Result;
+use bbqvec::{self, backend::VectorBackend, Quantization, ID};
+
+fn shard(
+    vecs: &[bbqvec::Vector],
+    ids: impl Iterator<Item = ID>,
+    bases: Option<Vec<bbqvec::Basis>>,
+) -> Result<bbqvec::VectorStore<bbqvec::MemoryBackend, bbqvec::CRoaringTreemap>> {
+    let mem = bbqvec::MemoryBackend::new(20, 6)?;
+    let mut store = match bases {
+        Some(b) => bbqvec::VectorStore::new_vector_store_with_bases(mem, b)?,
+        None => bbqvec::VectorStore::new_croaring_treemap(mem)?,
+    };
+    for id in ids {
+        store.add_vector(id, &vecs[id as usize])?;
+    }
+    Ok(store)
+}
+
+#[test]
+fn merge_with_shared_bases() -> Result<()> {
+    let vecs = bbqvec::create_vector_set(20, 2000);
+    let mut a = shard(&vecs, 0..1000, None)?;
+    let b = shard(&vecs, 900..2000, Some(a.bases().to_vec()))?;
+    // IDs 900..1000 are in both; move b's copies up past the end.
+    let merged = a.merge(&b, |id| Some(id + 10_000))?;
+    assert_eq!(merged, 1100);
+    assert_eq!(a.backend().info().vector_count, 2100);
+    assert!(a.backend().vector_exists(10_950));
+
+    // The combined bitmaps find what a store built in one go would.
+    let mut whole = shard(&vecs, 0..2000, Some(a.bases().to_vec()))?;
+    for id in 900..1000 {
+        whole.add_vector(id + 10_000, &vecs[id as usize])?;
+    }
+    let target = bbqvec::create_random_vector(20);
+    let ids = |rs: &bbqvec::ResultSet| rs.iter_results().map(|r| r.id).collect::<Vec<_>>();
+    assert_eq!(
+        ids(&a.find_nearest(&target, 10, 100, 2)?),
+        ids(&whole.find_nearest(&target, 10, 100, 2)?)
+    );
+    let rs = a.find_nearest(&vecs[1500], 1, 1, 0)?;
+    assert_eq!(rs.iter_results().next().unwrap().id, 1500);
+    Ok(())
+}
+
+#[test]
+fn merge_reprojects_and_skips() -> Result<()> {
+    let vecs = bbqvec::create_vector_set(20, 400);
+    let mut a = shard(&vecs, 0..200, None)?;
+    let b = shard(&vecs, 150..400, None)?;
+    assert_ne!(a.bases(), b.bases());
+    // Keep a's copy of anything both have.
+    let merged = a.merge(&b, |_| None)?;
+    assert_eq!(merged, 200);
+    assert_eq!(a.backend().info().vector_count, 400);
+    let rs = a.find_nearest(&vecs[300], 1, 1, 0)?;
+    assert_eq!(rs.iter_results().next().unwrap().id, 300);
+
+    let other = bbqvec::VectorStore::new_croaring_treemap(bbqvec::MemoryBackend::new(10, 6)?)?;
+    assert!(a.merge(&other, Some).is_err());
+    Ok(())
+}
+
+#[test]
+fn merge_rejects_other_training() -> Result<()> {
+    type Int8Memory = bbqvec::QuantizedMemoryBackend<bbqvec::Int8Quantization>;
+    let vecs = bbqvec::create_vector_set(20, 400);
+    let store = |sample: &[bbqvec::Vector]| -> Result<_> {
+        let mut q = bbqvec::Int8Quantization::default();
+        q.train(sample)?;
+        let mut store =
+            bbqvec::VectorStore::new_croaring_treemap(Int8Memory::with_quantizer(20, 6, q)?)?;
+        for (id, v) in sample.iter().enumerate() {
+            store.add_vector(id as ID, v)?;
+        }
+        Ok(store)
+    };
+    let mut a = store(&vecs[..200])?;
+    // Same name, different ranges.
+    let b = store(&vecs[200..])?;
+    assert!(a.merge(&b, |id| Some(id + 1000)).is_err());
+    assert_eq!(a.backend().info().vector_count, 200);
+    let same = store(&vecs[..200])?;
+    assert_eq!(a.merge(&same, |_| None)?, 0);
+    Ok(())
+}
+
+#[test]
+fn failed_merge_writes_nothing() -> Result<()> {
+    let vecs = bbqvec::create_vector_set(20, 300);
+    let mut a = shard(&vecs, 0..100, None)?;
+    let b = shard(&vecs, 50..300, Some(a.bases().to_vec()))?;
+    // The last collision is moved onto an ID that's already taken.
+    let err = a.merge(&b, |id| Some(if id == 99 { 10 } else { id + 1000 }));
+    assert!(err.is_err());
+    assert_eq!(a.backend().info().vector_count, 100);
+    assert!(!a.backend().vector_exists(200));
+    // Two collisions are moved onto the same ID.
+    assert!(a.merge(&b, |id| Some(1000 + id % 40)).is_err());
+    assert_eq!(a.backend().info().vector_count, 100);
+
+    // Whatever is stored can be found.
+    a.merge(&b, |id| Some(id + 1000))?;
+    for id in a.backend().iter_vector_ids().collect::<Vec<_>>() {
+        let v = a.backend().get_vector(id)?;
+        // Moved collisions are copies, so allow for their twin.
+        let rs = a.find_nearest(&v, 2, 1, 0)?;
+        assert!(rs.iter_results().any(|r| r.id == id));
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use anyhow::Result;
+use bbqvec::{
+    self, backend::VectorBackend, DirectorySource, Follower, IndexIDIterator, LogSource,
+    StreamSource,
+};
+
+type Leader =
+    bbqvec::VectorStore<bbqvec::DiskBackend<bbqvec::NoQuantization>, bbqvec::CRoaringTreemap>;
+
+fn leader(dir: &std::path::Path) -> Result<Leader> {
+    let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(dir.join("index"), 20, 4)?;
+    let mut store = bbqvec::VectorStore::new_croaring_treemap(be)?;
+    store.open_change_log(dir.join("log"))?;
+    Ok(store)
+}
+
+fn ids(rs: &bbqvec::ResultSet) -> Vec<u64> {
+    rs.iter_results().map(|r| r.id).collect()
+}
+
+#[test]
+fn follows_a_log_directory() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let vecs = bbqvec::create_vector_set(20, 300);
+    let mut leader = leader(dir.path())?;
+    leader.add_vector_iter(vecs.enumerate_ids().take(200))?;
+    leader.sync()?;
+
+    let standby = bbqvec::VectorStore::new(bbqvec::MemoryBackend::new(20, 4)?)?;
+    let mut follower = Follower::new(standby, DirectorySource::new(dir.path().join("log")))?;
+    assert_eq!(
+        follower.lag()?,
+        Some(leader.change_log().unwrap().last_seq())
+    );
+    // Bases, then 200 puts.
+    assert_eq!(follower.fetch()?, 201);
+    assert_eq!(follower.unapplied(), 201);
+    assert_eq!(follower.apply(50)?, 50);
+    assert_eq!(follower.unapplied(), 151);
+    assert_eq!(follower.lag()?, Some(151));
+    assert_eq!(follower.catch_up()?, 151);
+    assert_eq!(follower.unapplied(), 0);
+    assert_eq!(follower.lag()?, Some(0));
+    assert_eq!(follower.store().bases(), leader.bases());
+
+    leader.add_vector_iter(vecs.enumerate_ids().skip(200))?;
+    leader.remove_vector(10)?;
+    assert_eq!(follower.lag()?, Some(101));
+    assert_eq!(follower.catch_up()?, 101);
+    assert_eq!(
+        follower.applied_seq(),
+        leader.change_log().unwrap().last_seq()
+    );
+    assert!(!follower.store().backend().vector_exists(10));
+    let target = bbqvec::create_random_vector(20);
+    assert_eq!(
+        ids(&follower.store().find_nearest(&target, 10, 50, 2)?),
+        ids(&leader.find_nearest(&target, 10, 50, 2)?)
+    );
+
+    // The promoted standby takes writes of its own.
+    let mut promoted = follower.promote()?;
+    promoted.add_vector(1000, &target)?;
+    assert_eq!(promoted.backend().info().vector_count, 300);
+    Ok(())
+}
+
+#[test]
+fn failed_changes_stay_pending() -> Result<()> {
+    let changes = vec![
+        bbqvec::Change {
+            seq: 1,
+            event: bbqvec::ChangeEvent::Put {
+                id: 1,
+                vector: bbqvec::create_random_vector(20),
+            },
+        },
+        // The standby has 20 dimensions, so this can't apply.
+        bbqvec::Change {
+            seq: 2,
+            event: bbqvec::ChangeEvent::Put {
+                id: 2,
+                vector: bbqvec::create_random_vector(10),
+            },
+        },
+    ];
+    let mut shipped = Vec::new();
+    bbqvec::write_changes(changes, &mut shipped)?;
+    let standby = bbqvec::VectorStore::new(bbqvec::MemoryBackend::new(20, 4)?)?;
+    let mut follower = Follower::new(standby, StreamSource::new(&shipped[..]))?;
+    assert!(follower.catch_up().is_err());
+    assert_eq!(follower.applied_seq(), 1);
+    assert_eq!(follower.unapplied(), 1);
+    assert!(follower.apply(1).is_err());
+    assert_eq!(follower.unapplied(), 1);
+    Ok(())
+}
+
+#[test]
+fn ttls_reach_the_follower() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let vecs = bbqvec::create_vector_set(20, 50);
+    // Whole milliseconds, so the logged time round-trips exactly.
+    let past = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(dir.path().join("index"), 20, 4)?;
+    let mut leader = bbqvec::VectorStore::new_croaring_treemap(be)?;
+    leader.add_vector_iter(vecs.enumerate_ids())?;
+    leader.add_vector_with_expiry(3, &vecs[3], past)?;
+    // TTLs set before the log opened come over in its initial dump.
+    leader.open_change_log(dir.path().join("log"))?;
+    leader.add_vector_with_expiry(4, &vecs[4], past)?;
+
+    let standby = bbqvec::VectorStore::new(bbqvec::MemoryBackend::new(20, 4)?)?;
+    let mut follower = Follower::new(standby, DirectorySource::new(dir.path().join("log")))?;
+    follower.catch_up()?;
+    assert_eq!(follower.store().expires_at(3), leader.expires_at(3));
+    assert_eq!(follower.store().expires_at(4), leader.expires_at(4));
+    assert_eq!(follower.store().full_table_scan(&vecs[3], 50)?.len(), 48);
+    Ok(())
+}
+
+#[test]
+fn follows_a_byte_stream_and_resumes() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let vecs = bbqvec::create_vector_set(20, 100);
+    let mut leader = leader(dir.path())?;
+    leader.add_vector_iter(vecs.enumerate_ids())?;
+    let mut shipped = Vec::new();
+    bbqvec::write_changes(leader.change_log().unwrap().changes_since(0)?, &mut shipped)?;
+
+    let standby_dir = dir.path().join("standby");
+    {
+        let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(standby_dir.clone(), 20, 4)?;
+        let standby = bbqvec::VectorStore::new(be)?;
+        // Only the first half has arrived so far.
+        let half = &shipped[..shipped.len() / 2];
+        let mut follower = Follower::new(standby, StreamSource::new(half))?;
+        follower.catch_up()?;
+        assert!(follower.applied_seq() > 0 && follower.applied_seq() < 101);
+        follower.sync()?;
+    }
+    let be = bbqvec::DiskBackend::<bbqvec::NoQuantization>::open(standby_dir, 20, 4)?;
+    let standby = bbqvec::VectorStore::new(be)?;
+    // Replaying from the start skips what was already applied.
+    let mut follower = Follower::new(standby, StreamSource::new(&shipped[..]))?;
+    let resumed_at = follower.applied_seq();
+    assert_eq!(follower.catch_up()? as u64, 101 - resumed_at);
+    assert_eq!(follower.store().backend().info().vector_count, 100);
+    Ok(())
+}
+
+#[test]
+fn directory_source_keeps_what_it_cannot_read() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let vecs = bbqvec::create_vector_set(20, 10);
+    let mut leader = leader(dir.path())?;
+    leader.add_vector_iter(vecs.enumerate_ids())?;
+    leader.sync()?;
+    let path = dir.path().join("log").join("changes.jsonl");
+    let good = std::fs::read(&path)?;
+
+    // A corrupt line fails the poll without skipping past it.
+    let mut bad = good.clone();
+    bad.splice(0..0, b"{\"seq\":0}\n".iter().copied());
+    std::fs::write(&path, &bad)?;
+    let mut source = DirectorySource::new(dir.path().join("log"));
+    assert!(source.poll().is_err());
+    std::fs::write(&path, &good)?;
+    assert_eq!(source.poll()?.len(), 11);
+    assert_eq!(source.leader_seq()?, Some(11));
+
+    // A log cut short under the reader is an error, not a silent gap.
+    std::fs::write(&path, &good[..good.len() / 2])?;
+    assert!(source.poll().is_err());
+    Ok(())
+}
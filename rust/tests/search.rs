@@ -1,5 +1,5 @@
 use anyhow::Result;
-use bbqvec::{self, IndexIDIterator};
+use bbqvec::{self, IndexIDIterator, VectorBackend};
 
 #[test]
 fn search_index() -> Result<()> {
@@ -16,3 +16,319 @@ fn search_index() -> Result<()> {
     }
     Ok(())
 }
+
+#[test]
+fn best_effort_cursor_pages() -> Result<()> {
+    let data = bbqvec::create_vector_set(10, 5000);
+    let mem = bbqvec::MemoryBackend::new(10, 6)?;
+    let mut store = bbqvec::VectorStore::new(mem)?;
+    store.add_vector_iter(data.enumerate_ids())?;
+    let target = bbqvec::create_random_vector(10);
+
+    let expected = store.find_nearest(&target, 10, 100, 1)?;
+    let mut cursor = store.best_effort_cursor(&target, 100, 1)?;
+    let first = cursor.next_page(10)?;
+    assert_eq!(
+        first.iter().map(|r| r.id).collect::<Vec<_>>(),
+        expected.iter_results().map(|r| r.id).collect::<Vec<_>>()
+    );
+
+    let mut seen: std::collections::HashSet<_> = first.iter().map(|r| r.id).collect();
+    loop {
+        let page = cursor.next_page(250)?;
+        if page.is_empty() {
+            break;
+        }
+        for r in page {
+            assert!(seen.insert(r.id), "{} returned twice", r.id);
+        }
+    }
+    assert_eq!(seen.len(), cursor.returned());
+    // Widening past the first counting layer reaches well beyond search_k.
+    assert!(cursor.returned() > 100);
+    Ok(())
+}
+
+#[test]
+fn mmr_spreads_results() -> Result<()> {
+    let mut mem = bbqvec::MemoryBackend::new(4, 2)?;
+    // Five near-copies of one direction, and one further away.
+    for i in 0..5 {
+        mem.put_vector(i, &vec![1.0, 0.001 * i as f32, 0.0, 0.0])?;
+    }
+    mem.put_vector(5, &vec![0.6, 0.8, 0.0, 0.0])?;
+    let target = vec![0.9, 0.3, 0.0, 0.0];
+
+    let plain = mem.find_nearest(&target, 6)?;
+    let ids = |rs: &bbqvec::ResultSet| rs.iter_results().map(|r| r.id).collect::<Vec<_>>();
+    assert!(!ids(&plain)[..2].contains(&5));
+
+    let diverse = plain.rerank_mmr(&mem, 2, 0.5)?;
+    assert_eq!(diverse.len(), 2);
+    assert_eq!(ids(&diverse)[0], ids(&plain)[0]);
+    assert_eq!(ids(&diverse)[1], 5);
+    // The picks stay in order of similarity, so they merge like any set,
+    // even though the far vector was picked before the last near-copy.
+    let three = plain.rerank_mmr(&mem, 3, 0.5)?;
+    assert_eq!(ids(&three)[2], 5);
+    let sims: Vec<f32> = three.iter_results().map(|r| r.similarity).collect();
+    assert!(sims.windows(2).all(|w| w[0] >= w[1]));
+
+    // A lambda of one keeps the original ranking.
+    assert_eq!(ids(&plain.rerank_mmr(&mem, 6, 1.0)?), ids(&plain));
+    Ok(())
+}
+
+#[test]
+fn find_nearest_with_mmr() -> Result<()> {
+    let data = bbqvec::create_vector_set(10, 5000);
+    let mem = bbqvec::MemoryBackend::new(10, 6)?;
+    let mut store = bbqvec::VectorStore::new(mem)?;
+    store.add_vector_iter(data.enumerate_ids())?;
+    let target = bbqvec::create_random_vector(10);
+
+    let plain = store.find_nearest(&target, 10, 200, 1)?;
+    let opts = bbqvec::SearchOptions::new(10, 200, 1).with_mmr(0.5);
+    let diverse = store.find_nearest_with(&target, &opts)?;
+    assert_eq!(diverse.len(), 10);
+    let first = |rs: &bbqvec::ResultSet| rs.iter_results().next().unwrap().id;
+    assert_eq!(first(&plain), first(&diverse));
+    Ok(())
+}
+
+#[test]
+fn multi_vector_query() -> Result<()> {
+    let data = bbqvec::create_vector_set(10, 5000);
+    let mem = bbqvec::MemoryBackend::new(10, 6)?;
+    let mut store = bbqvec::VectorStore::new(mem)?;
+    store.add_vector_iter(data.enumerate_ids())?;
+    let target = bbqvec::create_random_vector(10);
+    let opts = bbqvec::SearchOptions::new(10, 100, 1);
+
+    // A single positive example is a plain search.
+    let plain = store.find_nearest_with(&target, &opts)?;
+    let single = store.find_nearest_query(&target.clone().into(), &opts)?;
+    let ids = |rs: &bbqvec::ResultSet| rs.iter_results().map(|r| r.id).collect::<Vec<_>>();
+    assert_eq!(ids(&plain), ids(&single));
+
+    // Pushing away from the best hit drops it from the results.
+    let best = &data[ids(&plain)[0] as usize];
+    let query = bbqvec::Query::new()
+        .positive(target.clone(), 1.0)
+        .positive(bbqvec::create_random_vector(10), 0.5)
+        .negative(best.clone(), 2.0);
+    let rs = store.find_nearest_query(&query, &opts)?;
+    assert_eq!(rs.len(), 10);
+    assert!(!ids(&rs).contains(&ids(&plain)[0]));
+
+    assert!(store
+        .find_nearest_query(&bbqvec::Query::new(), &opts)
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn negative_examples_pick_faces() -> Result<()> {
+    let mem = bbqvec::MemoryBackend::new(2, 1)?;
+    let mut store = bbqvec::VectorStore::new(mem)?;
+    // Opposite vectors always land in different faces.
+    store.add_vector(0, &vec![1.0, 0.0])?;
+    store.add_vector(1, &vec![-1.0, 0.0])?;
+    let query = bbqvec::Query::new()
+        .positive(vec![1.0, 0.0], 1.0)
+        .negative(vec![-1.0, 0.0], 0.1);
+    let rs = store.find_nearest_query(&query, &bbqvec::SearchOptions::new(2, 2, 0))?;
+    assert_eq!(rs.len(), 2);
+    assert_eq!(rs.iter_results().next().unwrap().id, 0);
+    Ok(())
+}
+
+#[test]
+fn search_budgets() -> Result<()> {
+    let data = bbqvec::create_vector_set(10, 5000);
+    let mem = bbqvec::MemoryBackend::new(10, 6)?;
+    let mut store = bbqvec::VectorStore::new(mem)?;
+    store.add_vector_iter(data.enumerate_ids())?;
+    let target = bbqvec::create_random_vector(10);
+    let opts = bbqvec::SearchOptions::new(10, 500, 1);
+
+    let full = store.find_nearest_with(&target, &opts)?;
+    assert!(!full.partial);
+
+    let rs = store.find_nearest_with(&target, &opts.clone().with_max_candidates(50))?;
+    assert!(rs.partial);
+    assert_eq!(rs.checked, 50);
+    assert_eq!(rs.len(), 10);
+
+    // A budget that is never reached changes nothing.
+    let rs = store.find_nearest_with(&target, &opts.clone().with_max_candidates(1_000_000))?;
+    assert!(!rs.partial);
+    assert_eq!(rs.compute_recall(&full, 10), 1.0);
+
+    let token = bbqvec::CancellationToken::new();
+    token.cancel();
+    let rs = store.find_nearest_with(&target, &opts.clone().with_cancellation(token.child()))?;
+    assert!(rs.partial);
+    assert!(rs.is_empty());
+
+    let past = std::time::Instant::now();
+    let rs = store.find_nearest_with(&target, &opts.with_deadline(past))?;
+    assert!(rs.partial);
+    assert_eq!(rs.checked, 0);
+    Ok(())
+}
+
+#[test]
+fn two_stage_rerank() -> Result<()> {
+    let data = bbqvec::create_vector_set(32, 5000);
+    let coarse = bbqvec::QuantizedMemoryBackend::<bbqvec::BF16Quantization>::new(32, 10)?;
+    let fine = bbqvec::MemoryBackend::new(32, 10)?;
+    let mut store = bbqvec::VectorStore::new(bbqvec::TwoStageBackend::new(coarse, fine)?)?;
+    store.add_vector_iter(data.enumerate_ids())?;
+    let target = bbqvec::create_random_vector(32);
+    let baseline = store.full_table_scan(&target, 10)?;
+
+    let opts = bbqvec::SearchOptions::new(10, 500, 4).with_rerank(50);
+    let rs = store.find_nearest_with(&target, &opts)?;
+    assert_eq!(rs.len(), 10);
+    // Reranked scores are the exact ones.
+    for r in rs.iter_results() {
+        let exact = store.backend().fine().compute_similarity(&target, r.id)?;
+        assert_eq!(r.similarity, exact);
+    }
+    let found = rs
+        .iter_results()
+        .filter(|r| baseline.iter_results().any(|b| b.id == r.id));
+    assert!(found.count() > 0);
+
+    // Reranking fewer than k still returns k results.
+    let opts = bbqvec::SearchOptions::new(10, 500, 4).with_rerank(5);
+    assert_eq!(store.find_nearest_with(&target, &opts)?.len(), 10);
+    Ok(())
+}
+
+#[test]
+fn two_stage_put_is_all_or_nothing() -> Result<()> {
+    // An untrained product quantizer can't lower anything, so the coarse
+    // write fails after the fine one succeeded.
+    let coarse = bbqvec::QuantizedMemoryBackend::<bbqvec::ProductQuantization<4>>::new(8, 2)?;
+    let fine = bbqvec::MemoryBackend::new(8, 2)?;
+    let mut be = bbqvec::TwoStageBackend::new(coarse, fine)?;
+    assert!(be.put_vector(1, &bbqvec::create_random_vector(8)).is_err());
+    assert!(!be.fine().vector_exists(1));
+    Ok(())
+}
+
+#[test]
+fn filtered_search() -> Result<()> {
+    let data = bbqvec::create_vector_set(10, 5000);
+    let mem = bbqvec::MemoryBackend::new(10, 6)?;
+    let mut store = bbqvec::VectorStore::new(mem)?;
+    store.add_vector_iter(data.enumerate_ids())?;
+    let mut payloads = bbqvec::PayloadStore::new();
+    for id in 0..data.len() as u64 {
+        let mut p = bbqvec::Payload::new();
+        p.insert("tenant".into(), (id % 4).to_string().into());
+        p.insert("ts".into(), (id as i64).into());
+        payloads.set(id, p)?;
+    }
+    let target = bbqvec::create_random_vector(10);
+    let opts = bbqvec::SearchOptions::new(10, 200, 2);
+
+    let filter = payloads.compile(&bbqvec::Filter::eq("tenant", "1"))?;
+    let rs = store.find_nearest_filtered(&target, &opts, &filter)?;
+    assert_eq!(rs.len(), 10);
+    assert!(rs.iter_results().all(|r| r.id % 4 == 1));
+
+    // A filter narrower than search_k scores everything that passes.
+    let f = bbqvec::Filter::eq("tenant", "2").and(bbqvec::Filter::range(
+        "ts",
+        bbqvec::Value::from(0)..bbqvec::Value::from(40),
+    ));
+    let filter = payloads.compile(&f)?;
+    let rs = store.find_nearest_filtered(&target, &opts, &filter)?;
+    assert!(!rs.is_empty());
+    assert!(rs.iter_results().all(|r| r.id % 4 == 2 && r.id < 40));
+    Ok(())
+}
+
+#[test]
+fn grouped_search() -> Result<()> {
+    let data = bbqvec::create_vector_set(10, 5000);
+    let mem = bbqvec::MemoryBackend::new(10, 6)?;
+    let mut store = bbqvec::VectorStore::new(mem)?;
+    store.add_vector_iter(data.enumerate_ids())?;
+    let target = bbqvec::create_random_vector(10);
+
+    // Fifty chunks to a document.
+    let groups = store.find_nearest_grouped(&target, 5, 3, 100, 1, |id| Some(id / 50))?;
+    assert_eq!(groups.len(), 5);
+    for (i, g) in groups.iter().enumerate() {
+        assert!(!g.hits.is_empty() && g.hits.len() <= 3);
+        assert!(g.hits.iter().all(|h| h.id / 50 == g.key));
+        assert!(g
+            .hits
+            .windows(2)
+            .all(|w| w[0].similarity >= w[1].similarity));
+        assert!(groups[i + 1..].iter().all(|o| o.key != g.key));
+        assert!(groups[i + 1..]
+            .iter()
+            .all(|o| o.similarity() <= g.similarity()));
+    }
+
+    // Ten documents of five hundred chunks each: the first candidates can't
+    // cover them all, so the search widens until it finds every one.
+    let groups = store.find_nearest_grouped(&target, 10, 1, 20, 1, |id| Some(id / 500))?;
+    assert_eq!(groups.len(), 10);
+    // Asking for more groups than exist returns what there is.
+    let groups = store.find_nearest_grouped(&target, 10, 1, 20, 1, |id| Some(id % 3))?;
+    assert_eq!(groups.len(), 3);
+
+    // The closest match sits just across a face boundary from the target,
+    // so it only turns up once the search spills over.
+    let mem = bbqvec::MemoryBackend::new(2, 1)?;
+    let basis = vec![vec![1.0, 0.0], vec![0.0, 1.0]];
+    let mut small: bbqvec::VectorStore<_, bbqvec::CRoaringBitmap> =
+        bbqvec::VectorStore::new_vector_store_with_bases(mem, vec![basis])?;
+    small.add_vector(0, &vec![1.0, -0.9])?;
+    small.add_vector(1, &vec![0.9, 1.0])?;
+    let groups = small.find_nearest_grouped(&vec![1.0, 0.9], 1, 1, 1, 0, Some)?;
+    assert_eq!(groups[0].key, 1);
+
+    let mut payloads = bbqvec::PayloadStore::<bbqvec::CRoaringBitmap>::new();
+    for id in 0..1000u64 {
+        let mut p = bbqvec::Payload::new();
+        p.insert("doc".into(), format!("doc-{}", id / 100).into());
+        payloads.set(id, p)?;
+    }
+    let groups = store.find_nearest_grouped(&target, 4, 2, 100, 1, payloads.group_key("doc"))?;
+    assert_eq!(groups.len(), 4);
+    for g in groups {
+        assert!(g.hits.iter().all(|h| h.id < 1000));
+        assert_eq!(g.key, format!("doc-{}", g.hits[0].id / 100).into());
+    }
+    Ok(())
+}
+
+#[test]
+fn binary_first_pass() -> Result<()> {
+    let data = bbqvec::create_vector_set(64, 5000);
+    let coarse = bbqvec::QuantizedMemoryBackend::<bbqvec::BinaryQuantization>::new(64, 10)?;
+    let fine = bbqvec::MemoryBackend::new(64, 10)?;
+    let mut store = bbqvec::VectorStore::new(bbqvec::TwoStageBackend::new(coarse, fine)?)?;
+    store.add_vector_iter(data.enumerate_ids())?;
+    let target = bbqvec::create_random_vector(64);
+    let baseline = store.full_table_scan(&target, 10)?;
+
+    let coarse_only = store.find_nearest_with(&target, &bbqvec::SearchOptions::new(10, 1000, 4))?;
+    let opts = bbqvec::SearchOptions::new(10, 1000, 4).with_rerank(200);
+    let rs = store.find_nearest_with(&target, &opts)?;
+    assert_eq!(rs.len(), 10);
+    for r in rs.iter_results() {
+        let exact = store.backend().fine().compute_similarity(&target, r.id)?;
+        assert_eq!(r.similarity, exact);
+    }
+    // Reranking recovers precision the sign bits lose.
+    assert!(rs.compute_recall(&baseline, 10) >= coarse_only.compute_recall(&baseline, 10));
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use bbqvec::{self, backend::VectorBackend, IndexIDIterator, ShardRouting, ShardedStore};
+
+fn shards(
+    n: usize,
+) -> Result<Vec<bbqvec::VectorStore<bbqvec::MemoryBackend, bbqvec::CRoaringBitmap>>> {
+    (0..n)
+        .map(|_| bbqvec::VectorStore::new_croaring_bitmap(bbqvec::MemoryBackend::new(20, 6)?))
+        .collect()
+}
+
+#[test]
+fn hash_sharded_search() -> Result<()> {
+    let vecs = bbqvec::create_vector_set(20, 4000);
+    let mut store = ShardedStore::new(shards(4)?, ShardRouting::Hash)?;
+    store.add_vector_iter(vecs.enumerate_ids())?;
+    assert_eq!(store.vector_count(), 4000);
+    for s in store.shards() {
+        // Roughly even, and nowhere near all on one shard.
+        assert!(s.backend().info().vector_count > 600);
+    }
+    let target = bbqvec::create_random_vector(20);
+    let baseline = store.full_table_scan(&target, 10)?;
+    assert_eq!(baseline.len(), 10);
+    assert_eq!(baseline.checked, 4000);
+    let rs = store.find_nearest(&target, 10, 200, 3)?;
+    assert_eq!(rs.len(), 10);
+    assert!(rs.compute_recall(&baseline, 10) > 0.5);
+
+    let best = baseline.iter_results().next().unwrap().id;
+    store.remove_vector(best)?;
+    let rs = store.full_table_scan(&target, 10)?;
+    assert!(rs.iter_results().all(|r| r.id != best));
+    Ok(())
+}
+
+#[test]
+fn range_sharding_gives_each_shard_its_own_ids() -> Result<()> {
+    let vecs = bbqvec::create_vector_set(20, 30);
+    let width = 1 << 32;
+    let mut store = ShardedStore::new(shards(3)?, ShardRouting::Range { width })?;
+    // Past what a single 32-bit bitmap can hold.
+    let ids: Vec<u64> = (0..30u64).map(|i| (i % 3) * width + i).collect();
+    store.add_vector_iter(ids.iter().copied().zip(vecs.iter()))?;
+    assert_eq!(store.locate(2 * width + 5), (2, 5));
+    assert_eq!(store.locate(7 * width), (2, 5 * width));
+    for s in store.shards() {
+        assert_eq!(s.backend().info().vector_count, 10);
+    }
+    let rs = store.find_nearest(&vecs[17], 1, 1, 0)?;
+    assert_eq!(rs.iter_results().next().unwrap().id, ids[17]);
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use bbqvec::{self, backend::VectorBackend, IndexIDIterator};
+
+#[test]
+fn snapshot_is_frozen() -> Result<()> {
+    let vecs = bbqvec::create_vector_set(20, 2000);
+    let mem = bbqvec::MemoryBackend::new(20, 6)?;
+    let mut store = bbqvec::VectorStore::new(mem)?;
+    store.add_vector_iter(vecs.enumerate_ids().take(1000))?;
+    let target = bbqvec::create_random_vector(20);
+
+    let snap = store.snapshot()?;
+    let before = snap.find_nearest(&target, 10, 50, 2)?;
+    let ids = |rs: &bbqvec::ResultSet| rs.iter_results().map(|r| r.id).collect::<Vec<_>>();
+
+    std::thread::scope(|s| -> Result<()> {
+        let reader = s.spawn(|| -> Result<()> {
+            for _ in 0..20 {
+                let rs = snap.find_nearest(&target, 10, 50, 2)?;
+                assert_eq!(ids(&rs), ids(&before));
+                assert_eq!(snap.full_table_scan(&target, 2000)?.len(), 1000);
+            }
+            Ok(())
+        });
+        store.add_vector_iter(vecs.enumerate_ids().skip(1000))?;
+        for id in ids(&before) {
+            store.remove_vector(id)?;
+        }
+        reader.join().unwrap()
+    })?;
+
+    assert_eq!(store.backend().info().vector_count, 1990);
+    assert_eq!(snap.backend().info().vector_count, 1000);
+    for id in ids(&before) {
+        assert!(snap.backend().vector_exists(id));
+        assert!(!store.backend().vector_exists(id));
+    }
+    let after = store.find_nearest(&target, 10, 50, 2)?;
+    assert!(ids(&after).iter().all(|id| !ids(&before).contains(id)));
+    Ok(())
+}
+
+#[test]
+fn disk_snapshot_is_frozen() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let vecs = bbqvec::create_vector_set(20, 400);
+    let be = bbqvec::DiskBackend::<bbqvec::BF16Quantization>::open(dir.path().into(), 20, 4)?;
+    let mut store = bbqvec::VectorStore::new(be)?;
+    store.add_vector_iter(vecs.enumerate_ids().take(200))?;
+    let target = bbqvec::create_random_vector(20);
+    let before = store.find_nearest(&target, 10, 50, 2)?;
+
+    let vec_files = || -> Result<usize> {
+        Ok(std::fs::read_dir(dir.path())?
+            .filter(|e| {
+                e.as_ref()
+                    .is_ok_and(|e| e.path().extension().is_some_and(|x| x == "vec"))
+            })
+            .count())
+    };
+    // Taking a snapshot copies nothing; the first write copies the file.
+    let snap = store.snapshot()?;
+    assert_eq!(vec_files()?, 1);
+    store.add_vector_iter(vecs.enumerate_ids().skip(200))?;
+    store.remove_vector(0)?;
+    assert_eq!(vec_files()?, 2);
+    assert_eq!(snap.backend().info().vector_count, 200);
+    assert!(snap.backend().vector_exists(0));
+    assert!(!snap.backend().vector_exists(300));
+    let rs = snap.find_nearest(&target, 10, 50, 2)?;
+    assert_eq!(
+        rs.iter_results().collect::<Vec<_>>(),
+        before.iter_results().collect::<Vec<_>>()
+    );
+    // The old file goes once the snapshot does.
+    drop(snap);
+    assert_eq!(vec_files()?, 1);
+    store.close()?;
+
+    let be = bbqvec::DiskBackend::<bbqvec::BF16Quantization>::open(dir.path().into(), 20, 4)?;
+    assert_eq!(be.info().vector_count, 399);
+    assert!(!be.vector_exists(0) && be.vector_exists(300));
+    Ok(())
+}
+
+#[test]
+fn snapshot_keeps_its_clock() -> Result<()> {
+    let vecs = bbqvec::create_vector_set(20, 10);
+    let mut store = bbqvec::VectorStore::new(bbqvec::MemoryBackend::new(20, 4)?)?;
+    store.add_vector_iter(vecs.enumerate_ids())?;
+    let soon = std::time::SystemTime::now() + std::time::Duration::from_millis(50);
+    store.add_vector_with_expiry(3, &vecs[3], soon)?;
+    let snap = store.snapshot()?;
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    // Still live as of the snapshot, though it has run out since.
+    assert_eq!(snap.full_table_scan(&vecs[3], 10)?.len(), 10);
+    assert_eq!(store.full_table_scan(&vecs[3], 10)?.len(), 9);
+    Ok(())
+}